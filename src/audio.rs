@@ -0,0 +1,326 @@
+use std::process::Command;
+
+// pulls the (start, end) intervals out of the file's audio and writes them one after another into out_location
+pub fn extract_intervals(file_location: &str, intervals: &[(f32, f32)], out_location: &str) {
+    // each interval is trimmed to the exact sample - selecting whole decoded frames would make every one a little off, and the error would add up over the ones after it
+    let mut filter: String = intervals
+        .iter()
+        .enumerate()
+        .map(|(i, (start, end))| {
+            format!(
+                "[0:a]atrim=start={}:end={},asetpts=PTS-STARTPTS[a{}];",
+                start, end, i
+            )
+        })
+        .collect();
+    filter += &(0..intervals.len())
+        .map(|i| format!("[a{}]", i))
+        .collect::<String>();
+    filter += &format!("concat=n={}:v=0:a=1[out]", intervals.len());
+
+    let out = Command::new("ffmpeg")
+        .arg("-y")
+        .args(["-i", file_location])
+        .args(["-filter_complex", &filter])
+        .args(["-map", "[out]"])
+        .arg(out_location)
+        .output()
+        .expect("FFmpeg error");
+
+    #[cfg(debug_assertions)]
+    println!("{:?}", out);
+}
+
+// how long each frame the voice activity detector looks at is, in seconds
+const FRAME: f32 = 0.03;
+// silences shorter than this are treated as part of the speech around them, in seconds
+const MIN_SILENCE: f32 = 0.5;
+// how much quiet is kept on either side of speech, so the soft starts and ends of words aren't lost, in seconds
+const PADDING: f32 = 0.2;
+
+// a stretch of speech cut out of the stream - the words that belong to it are the ones centered in its core, and the rest is overlap with the chunks next to it
+pub struct SpeechChunk {
+    // where the first sample is in the whole stream
+    pub start: usize,
+    pub core_start: usize,
+    pub core_end: usize,
+    pub samples: Vec<i16>,
+}
+
+// cuts a stream of samples into chunks of speech as it comes in, so the whole file never has to be in memory - long silences are left out, and speech too long for one chunk is split at its quietest point
+pub struct SpeechChunker {
+    threshold_db: f32,
+    frame: usize,
+    min_silence: usize,
+    padding: usize,
+    max_chunk: usize,
+    overlap: usize,
+    // samples not handed out yet, and where the first of them is in the whole stream
+    buffer: Vec<i16>,
+    start: usize,
+    // where the current chunk's core starts in the buffer - after the overlap, if the last chunk was split in the middle of speech
+    core_offset: usize,
+    // where the last loud frame ended in the buffer, if there's been one
+    last_loud: Option<usize>,
+    // samples that don't fill a whole frame yet
+    partial: Vec<i16>,
+}
+impl SpeechChunker {
+    pub fn new(
+        sample_rate: usize,
+        threshold_db: f32,
+        max_chunk: usize,
+        overlap: usize,
+    ) -> SpeechChunker {
+        SpeechChunker {
+            threshold_db,
+            frame: ((sample_rate as f32 * FRAME) as usize).max(1),
+            min_silence: (sample_rate as f32 * MIN_SILENCE) as usize,
+            padding: (sample_rate as f32 * PADDING) as usize,
+            // a chunk has to have room for its overlap on both sides
+            max_chunk: max_chunk.max(4 * overlap + 1),
+            overlap,
+            buffer: Vec::new(),
+            start: 0,
+            core_offset: 0,
+            last_loud: None,
+            partial: Vec::new(),
+        }
+    }
+
+    // adds samples to the stream, giving back any chunks they finished
+    pub fn push(&mut self, samples: &[i16]) -> Vec<SpeechChunk> {
+        let mut chunks = Vec::new();
+        self.partial.extend_from_slice(samples);
+
+        let whole = self.partial.len() - self.partial.len() % self.frame;
+        let frames: Vec<i16> = self.partial.drain(..whole).collect();
+
+        for frame in frames.chunks(self.frame) {
+            chunks.extend(self.push_frame(frame));
+        }
+
+        chunks
+    }
+
+    // the end of the stream - gives back whatever speech is left
+    pub fn finish(mut self) -> Vec<SpeechChunk> {
+        let partial = std::mem::take(&mut self.partial);
+        let mut chunks: Vec<SpeechChunk> = self.push_frame(&partial).into_iter().collect();
+
+        if self.last_loud.is_some() {
+            let end = self.buffer.len();
+            chunks.push(self.take_chunk(end, end, end));
+        }
+
+        chunks
+    }
+
+    fn push_frame(&mut self, frame: &[i16]) -> Option<SpeechChunk> {
+        if frame.is_empty() {
+            return None;
+        }
+
+        self.buffer.extend_from_slice(frame);
+
+        if loudness(frame) >= self.threshold_db {
+            self.last_loud = Some(self.buffer.len());
+        }
+
+        match self.last_loud {
+            // no speech yet - only the quiet right before it is kept
+            None => {
+                let extra = self.buffer.len().saturating_sub(self.padding);
+                self.buffer.drain(..extra);
+                self.start += extra;
+                None
+            }
+            // the speech stopped long enough ago to end the chunk
+            Some(last_loud) if self.buffer.len() - last_loud >= self.min_silence => {
+                let end = last_loud + self.padding;
+                let chunk = self.take_chunk(end, end, end);
+                self.last_loud = None;
+                self.core_offset = 0;
+                Some(chunk)
+            }
+            // too much speech without a break - it's split at its quietest point, with the pieces overlapping
+            Some(last_loud) if self.buffer.len() >= self.max_chunk => {
+                let split = quietest_frame(
+                    &self.buffer,
+                    self.frame,
+                    self.max_chunk / 2,
+                    self.max_chunk - self.overlap,
+                );
+                let chunk = self.take_chunk(split + self.overlap, split, split - self.overlap);
+                self.core_offset = self.overlap;
+                self.last_loud = Some(last_loud.saturating_sub(split - self.overlap));
+                Some(chunk)
+            }
+            Some(_) => None,
+        }
+    }
+
+    // hands out the buffer up to end as a chunk with its core ending at core_end, and keeps everything from keep on
+    fn take_chunk(&mut self, end: usize, core_end: usize, keep: usize) -> SpeechChunk {
+        let chunk = SpeechChunk {
+            start: self.start,
+            core_start: self.start + self.core_offset,
+            core_end: self.start + core_end,
+            samples: self.buffer[..end].to_vec(),
+        };
+
+        self.buffer.drain(..keep);
+        self.start += keep;
+
+        chunk
+    }
+}
+
+// the start of the quietest frame between from and to
+fn quietest_frame(samples: &[i16], frame: usize, from: usize, to: usize) -> usize {
+    (from..to.min(samples.len()))
+        .step_by(frame)
+        .min_by(|a, b| {
+            let a = loudness(&samples[*a..(a + frame).min(samples.len())]);
+            let b = loudness(&samples[*b..(b + frame).min(samples.len())]);
+            a.total_cmp(&b)
+        })
+        .unwrap_or(to)
+}
+
+// how loud the samples are, in dBFS - silence is negative infinity
+fn loudness(samples: &[i16]) -> f32 {
+    let mean_square: f64 =
+        samples.iter().map(|s| (*s as f64).powi(2)).sum::<f64>() / samples.len().max(1) as f64;
+
+    (20. * (mean_square.sqrt() / 32768.).log10()) as f32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 1000 samples a second makes the frames 30 samples, the minimum silence 500, and the padding 200
+    const RATE: usize = 1000;
+
+    fn loud(length: usize) -> Vec<i16> {
+        vec![10000; length]
+    }
+
+    fn quiet(length: usize) -> Vec<i16> {
+        vec![0; length]
+    }
+
+    fn chunk_all(
+        samples: &[i16],
+        block: usize,
+        max_chunk: usize,
+        overlap: usize,
+    ) -> Vec<SpeechChunk> {
+        let mut chunker = SpeechChunker::new(RATE, -40., max_chunk, overlap);
+        let mut chunks = Vec::new();
+
+        for piece in samples.chunks(block) {
+            chunks.extend(chunker.push(piece));
+        }
+        chunks.extend(chunker.finish());
+
+        chunks
+    }
+
+    #[test]
+    fn silence_has_no_chunks() {
+        assert!(chunk_all(&quiet(5000), 512, 10000, 0).is_empty());
+    }
+
+    #[test]
+    fn speech_is_padded_and_silence_is_left_out() {
+        let samples = [quiet(990), loud(990), quiet(1980)].concat();
+
+        let chunks = chunk_all(&samples, 512, 10000, 0);
+
+        assert_eq!(chunks.len(), 1);
+        assert_eq!(chunks[0].start, 790);
+        assert_eq!(chunks[0].core_start, 790);
+        assert_eq!(chunks[0].core_end, 2180);
+        assert_eq!(chunks[0].samples, samples[790..2180]);
+    }
+
+    #[test]
+    fn speech_at_the_end_is_kept() {
+        let samples = [quiet(990), loud(600)].concat();
+
+        let chunks = chunk_all(&samples, 512, 10000, 0);
+
+        assert_eq!(chunks.len(), 1);
+        assert_eq!(chunks[0].start, 790);
+        assert_eq!(chunks[0].core_end, 1590);
+        assert_eq!(chunks[0].samples.len(), 800);
+    }
+
+    #[test]
+    fn separate_speech_gets_separate_chunks() {
+        let samples = [loud(300), quiet(990), loud(300), quiet(990)].concat();
+
+        let chunks = chunk_all(&samples, 512, 10000, 0);
+
+        assert_eq!(chunks.len(), 2);
+        assert_eq!((chunks[0].start, chunks[0].core_end), (0, 500));
+        assert_eq!((chunks[1].start, chunks[1].core_end), (1090, 1790));
+    }
+
+    #[test]
+    fn long_speech_is_split_at_the_quietest_frame_with_overlap() {
+        // the only quiet frame is at 1200, inside where a split is looked for (900 to 1740)
+        let samples = [loud(1200), quiet(30), loud(870), quiet(990)].concat();
+
+        let chunks = chunk_all(&samples, 512, 1800, 60);
+
+        assert_eq!(chunks.len(), 2);
+
+        // the first chunk goes an overlap past the split, but its core ends at it
+        assert_eq!(chunks[0].start, 0);
+        assert_eq!(chunks[0].core_start, 0);
+        assert_eq!(chunks[0].core_end, 1200);
+        assert_eq!(chunks[0].samples, samples[..1260]);
+
+        // the second starts an overlap before the split, and its core picks up where the first one's ended
+        assert_eq!(chunks[1].start, 1140);
+        assert_eq!(chunks[1].core_start, 1200);
+        assert_eq!(chunks[1].core_end, 2300);
+        assert_eq!(chunks[1].samples, samples[1140..2300]);
+    }
+
+    #[test]
+    fn block_size_doesnt_change_the_chunks() {
+        let samples = [
+            quiet(700),
+            loud(1200),
+            quiet(30),
+            loud(870),
+            quiet(990),
+            loud(45),
+        ]
+        .concat();
+
+        let expected = chunk_all(&samples, samples.len(), 1800, 60);
+        let chunks = chunk_all(&samples, 7, 1800, 60);
+
+        assert_eq!(chunks.len(), expected.len());
+        for (a, b) in chunks.iter().zip(&expected) {
+            assert_eq!(
+                (a.start, a.core_start, a.core_end, &a.samples),
+                (b.start, b.core_start, b.core_end, &b.samples)
+            );
+        }
+    }
+
+    #[test]
+    fn quietest_frame_is_found() {
+        let samples = [loud(90), quiet(30), loud(90)].concat();
+
+        assert_eq!(quietest_frame(&samples, 30, 0, 210), 90);
+        // it's only looked for between from and to
+        assert_eq!(quietest_frame(&samples, 30, 150, 210), 150);
+    }
+}
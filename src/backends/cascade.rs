@@ -0,0 +1,212 @@
+use dirs::home_dir;
+use std::{fs, path::Path};
+
+use crate::backends::{Cleaner, Word};
+
+// runs a fast backend over the whole file, then has a more accurate one listen again to just the parts around anything that was or nearly was on the list
+pub struct Cascade {
+    fast: Box<dyn Cleaner>,
+    accurate: Box<dyn Cleaner>,
+    // how many seconds of audio to give the accurate backend on either side of a suspicious word
+    window: f32,
+}
+impl Cascade {
+    pub fn new(fast: Box<dyn Cleaner>, accurate: Box<dyn Cleaner>, window: f32) -> Cascade {
+        Cascade {
+            fast,
+            accurate,
+            window,
+        }
+    }
+}
+
+impl Cleaner for Cascade {
+    fn transcribe(&mut self, file_location: &str) -> Vec<Word> {
+        let words = self.fast.transcribe(file_location);
+        let expletives = crate::load_expletives();
+
+        let suspicious: Vec<&Word> = words
+            .iter()
+            .filter(|w| is_near_miss(&w.word, &expletives))
+            .collect();
+
+        if suspicious.is_empty() {
+            return words;
+        }
+
+        let windows = merge_windows(&suspicious, self.window);
+
+        // the windows are listened to back to back in one file, so the accurate backend only has to start up once
+        let temp_dir = home_dir()
+            .expect("Error getting user's home directory")
+            .join(".project-soap")
+            .join("temp");
+        fs::create_dir_all(&temp_dir).expect("Error making temp directory");
+
+        let file_stem = Path::new(file_location)
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .expect("Error getting file name");
+        let windows_location = temp_dir.join(format!("cascade-{}.wav", file_stem));
+        let windows_location = windows_location
+            .to_str()
+            .expect("Error converting temp path to string");
+
+        crate::audio::extract_intervals(file_location, &windows, windows_location);
+        let accurate_words = self.accurate.transcribe(windows_location);
+        let _ = fs::remove_file(windows_location);
+        // backends like WhisperX leave their transcript of the windows next to them
+        let _ = fs::remove_file(temp_dir.join(format!("cascade-{}.json", file_stem)));
+
+        // the accurate backend's word for anything inside a window, the fast one's for everything else
+        let mut merged: Vec<Word> = words
+            .into_iter()
+            .filter(|w| {
+                !windows
+                    .iter()
+                    .any(|(start, end)| w.start < *end && *start < w.end)
+            })
+            .collect();
+
+        merged.extend(
+            accurate_words
+                .into_iter()
+                .filter_map(|w| place_word(w, &windows)),
+        );
+
+        merged.sort_by(|a, b| a.start.total_cmp(&b.start));
+        merged
+    }
+
+    fn describe(&self) -> String {
+        // which parts get listened to again depends on the list, so a transcript made with an older list can't be reused
+        format!(
+            "cascade --fast {} --accurate {} --window {} --list {}",
+            self.fast.describe(),
+            self.accurate.describe(),
+            self.window,
+            crate::list_hash()
+        )
+    }
+}
+
+// merges the windows of audio around each suspicious word, so no audio is sent twice
+fn merge_windows(suspicious: &[&Word], window: f32) -> Vec<(f32, f32)> {
+    let mut windows: Vec<(f32, f32)> = suspicious
+        .iter()
+        .map(|w| ((w.start - window).max(0.), w.end + window))
+        .collect();
+    windows.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+    let mut merged: Vec<(f32, f32)> = Vec::new();
+    for window in windows {
+        match merged.last_mut() {
+            Some(last) if window.0 <= last.1 => last.1 = last.1.max(window.1),
+            _ => merged.push(window),
+        }
+    }
+
+    merged
+}
+
+// the windows are listened to back to back, so this finds which window the word came from and moves it back to where that window is in the file - None if it's past the end of the last one
+fn place_word(mut word: Word, windows: &[(f32, f32)]) -> Option<Word> {
+    let mut offset = 0.;
+
+    for (start, end) in windows {
+        let length = end - start;
+
+        if word.start < offset + length {
+            word.start += start - offset;
+            word.end += start - offset;
+            return Some(word);
+        }
+
+        offset += length;
+    }
+
+    None
+}
+
+// whether the word is on the list, or only one letter away from something that is - short words are left out, since nearly everything is one letter away from them
+fn is_near_miss(word: &str, expletives: &crate::Expletives) -> bool {
+    let word = word.to_lowercase();
+
+    expletives.contains_key(&word)
+        || expletives
+            .keys()
+            .any(|e| e.chars().count() >= 4 && edit_distance(&word, e) <= 1)
+}
+
+// the number of single letter insertions, deletions or substitutions it takes to turn a into b
+fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+
+    for i in 1..=a.len() {
+        let mut current = vec![i; b.len() + 1];
+
+        for j in 1..=b.len() {
+            let substitution = previous[j - 1] + if a[i - 1] == b[j - 1] { 0 } else { 1 };
+            current[j] = substitution.min(previous[j] + 1).min(current[j - 1] + 1);
+        }
+
+        previous = current;
+    }
+
+    previous[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn word(start: f32, end: f32) -> Word {
+        Word {
+            word: String::from("word"),
+            start,
+            end,
+            confidence: None,
+            speaker: None,
+        }
+    }
+
+    fn close(a: f32, b: f32) -> bool {
+        (a - b).abs() < 1e-4
+    }
+
+    #[test]
+    fn overlapping_windows_are_merged() {
+        let words = [word(10., 10.5), word(1., 1.2), word(1.5, 1.7)];
+        let suspicious: Vec<&Word> = words.iter().collect();
+
+        let windows = merge_windows(&suspicious, 1.);
+
+        assert_eq!(windows.len(), 2);
+        // the first window can't start before the file does
+        assert!(close(windows[0].0, 0.) && close(windows[0].1, 2.7));
+        assert!(close(windows[1].0, 9.) && close(windows[1].1, 11.5));
+    }
+
+    #[test]
+    fn words_are_put_back_where_their_window_is() {
+        let windows = [(0., 2.7), (9., 11.5), (20., 21.)];
+
+        // in the first window, nothing moves
+        let first = place_word(word(1., 1.2), &windows).unwrap();
+        assert!(close(first.start, 1.) && close(first.end, 1.2));
+
+        // 0.3 seconds into the second window, which starts 2.7 seconds into the windows played back to back
+        let second = place_word(word(3., 3.4), &windows).unwrap();
+        assert!(close(second.start, 9.3) && close(second.end, 9.7));
+
+        // 0.5 seconds into the third window, after 2.7 + 2.5 seconds of the first two
+        let third = place_word(word(5.7, 5.9), &windows).unwrap();
+        assert!(close(third.start, 20.5) && close(third.end, 20.7));
+
+        // past the end of everything the accurate backend was given
+        assert!(place_word(word(6.5, 6.7), &windows).is_none());
+    }
+}
@@ -0,0 +1,98 @@
+use crate::backends::{Cleaner, Word};
+use crate::cli::Vote;
+
+// runs several backends over the same file and votes on which of their words to keep
+pub struct Ensemble {
+    members: Vec<Box<dyn Cleaner>>,
+    vote: Vote,
+    // how confident a backend has to be for --vote confidence to take its word alone
+    min_confidence: f32,
+}
+impl Ensemble {
+    pub fn new(members: Vec<Box<dyn Cleaner>>, vote: Vote, min_confidence: f32) -> Ensemble {
+        Ensemble {
+            members,
+            vote,
+            min_confidence,
+        }
+    }
+
+    // whether enough backends agree on the word to keep it
+    fn keep(&self, word: &Word, member: usize, transcripts: &[Vec<Word>]) -> bool {
+        match self.vote {
+            Vote::Union => true,
+            Vote::Majority => {
+                let agreeing = transcripts
+                    .iter()
+                    .enumerate()
+                    .filter(|(i, words)| *i == member || words.iter().any(|w| same_word(w, word)))
+                    .count();
+
+                agreeing * 2 > transcripts.len()
+            }
+            // backends that don't give a confidence are taken at their word
+            Vote::Confidence => word.confidence.unwrap_or(1.) >= self.min_confidence,
+        }
+    }
+}
+
+impl Cleaner for Ensemble {
+    fn transcribe(&mut self, file_location: &str) -> Vec<Word> {
+        let transcripts: Vec<Vec<Word>> = self
+            .members
+            .iter_mut()
+            .map(|m| m.transcribe(file_location))
+            .collect();
+
+        let mut kept: Vec<Word> = Vec::new();
+
+        for (member, words) in transcripts.iter().enumerate() {
+            for word in words {
+                if !self.keep(word, member, &transcripts) {
+                    continue;
+                }
+
+                // only one copy of a word the backends agree on, with the highest confidence any of them gave it
+                match kept.iter_mut().find(|w| same_word(w, word)) {
+                    Some(existing) => {
+                        if word.confidence.unwrap_or(0.) > existing.confidence.unwrap_or(0.) {
+                            existing.confidence = word.confidence;
+                        }
+                        existing.start = existing.start.min(word.start);
+                        existing.end = existing.end.max(word.end);
+                    }
+                    None => kept.push(word.clone()),
+                }
+            }
+        }
+
+        kept.sort_by(|a, b| a.start.total_cmp(&b.start));
+        kept
+    }
+
+    fn describe(&self) -> String {
+        let members: Vec<String> = self
+            .members
+            .iter()
+            .map(|m| format!("[{}]", m.describe()))
+            .collect();
+
+        format!(
+            "ensemble --vote {} --vote-confidence {} {}",
+            format!("{:?}", self.vote).to_lowercase(),
+            self.min_confidence,
+            members.join(" ")
+        )
+    }
+}
+
+// two backends heard the same word if they wrote it the same way and their timestamps overlap
+fn same_word(a: &Word, b: &Word) -> bool {
+    a.start < b.end && b.start < a.end && normalize(&a.word) == normalize(&b.word)
+}
+
+// some backends keep punctuation and capitals, others don't
+fn normalize(word: &str) -> String {
+    word.trim_matches(|c: char| !c.is_alphanumeric() && c != '\'')
+        .to_lowercase()
+}
@@ -1,7 +1,13 @@
 use enum_dispatch::enum_dispatch;
+use serde::{Deserialize, Serialize};
 
+use crate::cli;
+
+pub mod cascade;
+pub mod ensemble;
 pub mod parakeet_local;
 pub mod vosk_local;
+pub mod vosk_models;
 pub mod whisperx_local;
 
 use parakeet_local::ParakeetLocal;
@@ -10,7 +16,64 @@ use whisperx_local::WhisperXLocal;
 
 #[enum_dispatch]
 pub trait Cleaner {
-    fn transcribe(&mut self) -> Vec<Word>;
+    fn transcribe(&mut self, file_location: &str) -> Vec<Word>;
+    // names the backend and the options that change its output - used to key the transcript cache
+    fn describe(&self) -> String;
+}
+
+// loads the backend picked on the command line, wrapping it up with any others it's combined with
+pub fn from_args(args: cli::Args) -> Option<Box<dyn Cleaner>> {
+    let cleaner = match args.backend {
+        Some(cli::Backend::VoskLocal { .. }) => VoskLocal::from_args(args.clone()),
+        Some(cli::Backend::WhisperXLocal { .. }) => WhisperXLocal::from_args(args.clone()),
+        Some(cli::Backend::ParakeetLocal { .. }) => ParakeetLocal::from_args(args.clone()),
+        None => {
+            println!("Please choose a backend");
+            None
+        }
+    }?;
+
+    let cleaner: Box<dyn Cleaner> = if args.ensemble.is_empty() {
+        cleaner
+    } else {
+        let mut members = vec![cleaner];
+        for backend in args.ensemble.clone() {
+            members.push(
+                from_args(cli::Args {
+                    backend: Some(backend),
+                    ensemble: Vec::new(),
+                    cascade: None,
+                    ..args.clone()
+                })
+                .expect("Error loading an ensemble backend"),
+            );
+        }
+
+        Box::new(ensemble::Ensemble::new(
+            members,
+            args.vote,
+            args.vote_confidence,
+        ))
+    };
+
+    match args.cascade.clone() {
+        Some(accurate) => {
+            let accurate = from_args(cli::Args {
+                backend: Some(accurate),
+                ensemble: Vec::new(),
+                cascade: None,
+                ..args.clone()
+            })
+            .expect("Error loading the cascade's accurate backend");
+
+            Some(Box::new(cascade::Cascade::new(
+                cleaner,
+                accurate,
+                args.cascade_window,
+            )))
+        }
+        None => Some(cleaner),
+    }
 }
 
 #[enum_dispatch(Cleaner)]
@@ -20,8 +83,15 @@ pub enum Backend {
     ParakeetLocal,
 }
 
+#[derive(Serialize, Deserialize, Clone)]
 pub struct Word {
     pub word: String,
     pub start: f32,
     pub end: f32,
+    // how sure the backend is about the word, if it says
+    #[serde(default)]
+    pub confidence: Option<f32>,
+    // who said it, if the backend can tell speakers apart
+    #[serde(default)]
+    pub speaker: Option<String>,
 }
@@ -1,33 +1,24 @@
 use crate::{backends::Cleaner, cli};
+use dirs::home_dir;
 use serde::{Deserialize, Serialize};
+use std::{fs, path::Path};
 
 // For FFmpeg
 use std::process::Command;
 
-pub struct ParakeetLocal {
-    // the path to the input file
-    file_location: String,
-    // the path where we'll put the preprocessed audio file - 16khz, 16 bit pcm wav
-    preprocessed_file_location: String,
-}
+pub struct ParakeetLocal {}
 impl ParakeetLocal {
-    pub fn from_args(args: cli::Args) -> Option<Box<dyn Cleaner>> {
-        let file_in = args.file_in.expect("No input file given");
-        let prep_fl = file_in.clone() + ".wav";
-
-        Some(Box::new(ParakeetLocal {
-            file_location: file_in,
-            preprocessed_file_location: prep_fl,
-        }))
+    pub fn from_args(_args: cli::Args) -> Option<Box<dyn Cleaner>> {
+        Some(Box::new(ParakeetLocal {}))
     }
 
     // preprocesses the input media file into a 16khz 16 bit mono pcm wav file for the model by using ffmpeg
-    fn preprocess_audio(&self) {
+    fn preprocess_audio(file_location: &str, preprocessed_file_location: &str) {
         let _ = Command::new("ffmpeg")
             // allows ffmpeg to run automatically
             .arg("-y")
             // tells ffmpeg the in file is at file_location
-            .args(["-i", &format!("{}", self.file_location)])
+            .args(["-i", file_location])
             // makes the audio 16khz
             .args(["-ar", "16000"])
             // makes the audio mono
@@ -35,7 +26,7 @@ impl ParakeetLocal {
             //this line is what ffmpeg does by default - basically, s16le is 16 bit pcm
             //.args(["-f", "s16le"])
             // sets the location of the temp audio file
-            .arg(self.preprocessed_file_location.clone())
+            .arg(preprocessed_file_location)
             .output()
             .expect("FFmpeg error");
     }
@@ -58,7 +49,13 @@ impl ParakeetLocal {
                 let start = value.start;
                 let end = value.end;
 
-                crate::backends::Word { word, start, end }
+                crate::backends::Word {
+                    word,
+                    start,
+                    end,
+                    confidence: None,
+                    speaker: None,
+                }
             }
         }
         //println!("{:?}", json_string);
@@ -73,16 +70,36 @@ impl ParakeetLocal {
 }
 
 impl Cleaner for ParakeetLocal {
-    fn transcribe(&mut self) -> Vec<super::Word> {
-        self.preprocess_audio();
+    fn transcribe(&mut self, file_location: &str) -> Vec<super::Word> {
+        // the path where we'll put the preprocessed audio file - 16khz, 16 bit pcm wav
+        // it goes in ~/.project-soap/temp, since next to the input it would get picked up as another file to clean by batches and --watch
+        let temp_dir = home_dir()
+            .expect("Error getting user's home directory")
+            .join(".project-soap")
+            .join("temp");
+        fs::create_dir_all(&temp_dir).expect("Error making temp directory");
+
+        let file_name = Path::new(file_location)
+            .file_name()
+            .and_then(|n| n.to_str())
+            .expect("Error getting file name");
+        let preprocessed_file_location = temp_dir
+            .join(format!("parakeet-{}.wav", file_name))
+            .to_str()
+            .expect("Error converting temp path to string")
+            .to_string();
+
+        ParakeetLocal::preprocess_audio(file_location, &preprocessed_file_location);
         let out = Command::new("uv")
             .arg("run")
             .args(["--project", "./src/backends/parakeet/"])
             .arg("./src/backends/parakeet/main.py")
-            .arg(self.preprocessed_file_location.clone())
+            .arg(preprocessed_file_location.clone())
             .output()
             .expect("Error running Parakeet");
 
+        let _ = fs::remove_file(&preprocessed_file_location);
+
         //println!("{:?}", out);
 
         let raw =
@@ -96,4 +113,8 @@ impl Cleaner for ParakeetLocal {
 
         return self.deserialize(&raw);
     }
+
+    fn describe(&self) -> String {
+        String::from("parakeet-local")
+    }
 }
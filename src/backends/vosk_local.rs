@@ -1,53 +1,132 @@
-use crate::{backends::Cleaner, cli};
+use crate::{
+    audio::{self, SpeechChunk},
+    backends::{vosk_models, Cleaner, Word},
+    cli,
+};
 
 // For multi-threading
-use serde_json::json;
+use std::collections::HashMap;
+use std::sync::{
+    mpsc::{self, Receiver},
+    Arc, Mutex, OnceLock,
+};
 use std::thread::JoinHandle;
 use std::{fs, thread, usize};
 
 // For FFmpeg
-use std::process::Command;
+use std::io::Read;
+use std::process::{Child, Command, Stdio};
 
 // For Vosk
-use hound;
-use vosk::{Model, Recognizer};
+use vosk::{
+    CompleteResult, CompleteResultMultiple, DecodingState, Model, Recognizer, SpeakerModel,
+};
 
 // For loading list of swear words
 
 use std::fs::File;
 use std::io;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
 // For getting models from the web
-use reqwest::blocking::Client;
-use std::io::Cursor;
+use dirs::home_dir;
+use indicatif::{ProgressBar, ProgressStyle};
+use reqwest::{blocking::Client, header::RANGE, StatusCode};
+use sha2::{Digest, Sha256};
+use std::fs::OpenOptions;
+use std::time::Duration;
 use zip::read::ZipArchive;
 
 pub struct VoskLocal {
     // the path to the model
     model_location: String,
-    // the path to the input file
-    file_location: String,
-    // the path where we'll put the preprocessed audio file - 16khz, 16 bit pcm wav
-    preprocessed_file_location: String,
+    // tells models apart even when a new one is installed where the old one was, so transcripts from the old one aren't reused
+    model_fingerprint: String,
+    // the model itself - loaded the first time it's needed and shared with everything else using the same model
+    model: Option<Arc<Model>>,
     // the number of threads to run the model on
     thread_number: usize,
-    temp_dir_name: String,
+    // how loud audio has to be to count as speech, in dBFS
+    silence_threshold: f32,
+    // how many seconds chunks split in the middle of speech overlap each other
+    overlap: f32,
+    // how many alternatives to ask the model for - 0 means just the best one
+    alternatives: u16,
+    // how likely an alternative has to be for its listed words to count
+    alternative_threshold: f32,
+    // listen again with only the list's words to anything less confident than this - None means don't
+    grammar_below: Option<f32>,
+    // the path to a speaker model, for telling who said what
+    speaker_model_location: Option<String>,
+    // the speaker model itself - loaded the first time it's needed
+    speaker_model: Option<Arc<SpeakerModel>>,
 }
+
+// every model loaded so far, by where it was loaded from - big models take a long time to load and a lot of memory to keep, so each one is only ever loaded once
+static MODELS: OnceLock<Mutex<HashMap<String, Arc<Model>>>> = OnceLock::new();
+
+// how many samples are fed to the model at a time, so each utterance comes back on its own - a quarter second at 16khz
+const PIECE: usize = 4000;
+
+// the longest a chunk can be before it's split, in samples - 30 seconds at 16khz
+const MAX_CHUNK: usize = 480000;
+
+// how many seconds on either side of an unsure word the grammar pass listens to
+const GRAMMAR_WINDOW: f32 = 0.5;
+
+// how alike two voices from the speaker model have to be to be the same person, from -1 to 1
+const SAME_SPEAKER: f32 = 0.5;
+
+// one utterance - the voice it was said in if there's a speaker model, and its words
+type Utterance = (Option<Vec<f32>>, Vec<Word>);
+
+// what a thread gives back for each chunk - where it starts, and the utterances in it
+type ChunkResult = (usize, Vec<Utterance>);
+
+// how much audio is read from ffmpeg at a time, in samples - half a second at 16khz
+const BLOCK: usize = 8000;
+
 impl VoskLocal {
     pub fn from_args(args: cli::Args) -> Option<Box<dyn Cleaner>> {
         let c;
         let m: String;
+        let silence_threshold;
+        let overlap;
+        let alternatives;
+        let alternative_threshold;
+        let grammar_below;
+        let speaker_model_location;
 
         match args.backend {
-            cli::Backend::VoskLocal { model, command } => {
+            Some(cli::Backend::VoskLocal {
+                model,
+                silence_threshold: s,
+                overlap: o,
+                alternatives: a,
+                alternative_threshold: t,
+                grammar_pass,
+                grammar_below: g,
+                speaker_model,
+                command,
+            }) => {
                 m = model;
+                silence_threshold = s;
+                overlap = o;
+                alternatives = a;
+                alternative_threshold = t;
+                grammar_below = if grammar_pass { Some(g) } else { None };
+                speaker_model_location = speaker_model;
                 c = command;
             }
 
             _ => panic!("VoskLocal tried to initialize when other backend selected"),
         }
 
+        // vosk only gives voices with single results, so every word would come back without a speaker
+        if speaker_model_location.is_some() && alternatives > 0 {
+            panic!("--speaker-model doesn't work with --alternatives - Vosk can't tell who said what when it's giving alternatives");
+        }
+
         if c.is_some() {
             let command = c.as_ref().unwrap();
 
@@ -56,139 +135,394 @@ impl VoskLocal {
                     small,
                     medium,
                     large,
+                    from_file,
+                    from_dir,
+                    sha256,
                 } => {
-                    if *small {
-                        VoskLocal::get_model("small", m);
+                    if let Some(zip_location) = from_file {
+                        VoskLocal::get_model_from_file(zip_location, m);
+                    } else if let Some(dir) = from_dir {
+                        VoskLocal::get_model_from_dir(dir, m);
+                    } else if *small {
+                        VoskLocal::get_model("small", m, sha256.as_deref());
                     } else if *medium {
-                        VoskLocal::get_model("medium", m);
+                        VoskLocal::get_model("medium", m, sha256.as_deref());
                     } else if *large {
-                        VoskLocal::get_model("large", m);
+                        VoskLocal::get_model("large", m, sha256.as_deref());
                     }
                 }
+                VoskLocalCommands::Models { command } => vosk_models::run(command),
             }
 
             return None;
         }
 
-        let file_in = args.file_in.expect("No input file given");
-
-        let san_file_in = file_in.replace("'", "");
-
-        if file_in.contains("'") {
-            std::fs::rename(file_in, san_file_in.clone()).expect("Error moving file");
-        }
-
-        // gets the file's name by itself
-        let path = Path::new(&san_file_in);
-        let file_name = path
-            .file_name()
-            .and_then(|name| name.to_str())
-            .expect("Error getting file name")
-            .to_string();
-
-        #[cfg(unix)]
-        let temp_dir_name = format!("./temp'{}'", file_name);
-
-        #[cfg(windows)]
-        let temp_dir_name = format!(".\\temp'{}'", file_name);
-
-        // start by making the temp directory - without this, writing the temp files will fail
-        VoskLocal::make_temp_dir(temp_dir_name.clone());
+        // when --model wasn't changed, the model set with "models default" is used instead, if there is one
+        let m = match vosk_models::default_location() {
+            Some(default) if m == cli::default_model_location() => default,
+            _ => m,
+        };
 
         // makes and returns the Cleaner struct
         Some(Box::new(VoskLocal {
+            model_fingerprint: vosk_models::model_fingerprint(Path::new(&m)),
             model_location: m,
-            file_location: san_file_in,
-            preprocessed_file_location: format!(
-                "{}\\{}.wav",
-                temp_dir_name.clone(),
-                file_name.clone()
-            ),
+            model: None,
             thread_number: args.threads,
-            temp_dir_name,
+            silence_threshold,
+            overlap,
+            alternatives,
+            alternative_threshold,
+            grammar_below,
+            speaker_model_location,
+            speaker_model: None,
         }))
     }
 
-    // preprocesses the input media file into a 16khz 16 bit mono pcm wav file for the model by using ffmpeg
-    fn preprocess_audio(&self) {
-        let out = Command::new("ffmpeg")
-            // allows ffmpeg to run automatically
-            .arg("-y")
+    // starts ffmpeg turning the input media file into 16khz 16 bit mono pcm, which it streams out to us instead of writing to a file
+    fn stream_audio(file_location: &str) -> Child {
+        Command::new("ffmpeg")
+            // only tells us about errors
+            .args(["-v", "error"])
             // tells ffmpeg the in file is at file_location
-            .args(["-i", &format!("{}", self.file_location)])
+            .args(["-i", file_location])
             // makes the audio 16khz
             .args(["-ar", "16000"])
             // makes the audio mono
             .args(["-ac", "1"])
-            //this line is what ffmpeg does by default - basically, s16le is 16 bit pcm
-            //.args(["-f", "s16le"])
-            // sets the location of the temp audio file
-            .arg(self.preprocessed_file_location.clone())
-            .output()
-            .expect("FFmpeg error");
+            // basically, s16le is 16 bit pcm, with nothing around it
+            .args(["-f", "s16le"])
+            // sends it to stdout
+            .arg("-")
+            .stdout(Stdio::piped())
+            .spawn()
+            .expect("FFmpeg error")
+    }
 
-        #[cfg(debug_assertions)]
-        println!("{:?}", out);
+    // gets the model at model_location, loading it if nothing else has yet
+    fn shared_model(model_location: &str) -> Arc<Model> {
+        // the same model can be reached by different paths
+        let key = fs::canonicalize(model_location)
+            .map(|p| p.display().to_string())
+            .unwrap_or_else(|_| model_location.to_string());
+
+        let mut models = MODELS
+            .get_or_init(|| Mutex::new(HashMap::new()))
+            .lock()
+            .expect("Error locking loaded models");
+
+        Arc::clone(models.entry(key).or_insert_with(|| {
+            println!("Loading model at {} ...", model_location);
+            Arc::new(Model::new(model_location).expect("Could not create model"))
+        }))
+    }
+
+    // finds which of the voices heard so far this one belongs to, or adds it as a new one - each voice is kept as the sum of everything that matched it
+    fn which_speaker(voices: &mut Vec<Vec<f32>>, voice: Vec<f32>) -> usize {
+        let best = voices
+            .iter()
+            .enumerate()
+            .map(|(i, v)| (i, cosine_similarity(v, &voice)))
+            .max_by(|a, b| a.1.total_cmp(&b.1));
+
+        match best {
+            Some((i, similarity)) if similarity >= SAME_SPEAKER => {
+                for (sum, value) in voices[i].iter_mut().zip(&voice) {
+                    *sum += value;
+                }
+                i
+            }
+            _ => {
+                voices.push(voice);
+                voices.len() - 1
+            }
+        }
+    }
+
+    // reads the audio from ffmpeg a block at a time, handing each block to use as it comes in
+    fn read_audio(file_location: &str, mut use_block: impl FnMut(&[i16])) {
+        let mut ffmpeg = VoskLocal::stream_audio(file_location);
+        let mut stdout = ffmpeg.stdout.take().expect("Error getting FFmpeg's output");
+
+        let mut block = vec![0u8; BLOCK * 2];
+        // how much of the block has been read into - a sample can be split between two reads
+        let mut filled = 0;
+
+        loop {
+            let read = stdout
+                .read(&mut block[filled..])
+                .expect("Error reading audio from FFmpeg");
+            if read == 0 {
+                break;
+            }
+            filled += read;
+
+            let whole = filled - filled % 2;
+            let samples: Vec<i16> = block[..whole]
+                .chunks_exact(2)
+                .map(|b| i16::from_le_bytes([b[0], b[1]]))
+                .collect();
+            block.copy_within(whole..filled, 0);
+            filled -= whole;
+
+            use_block(&samples);
+        }
+
+        if !ffmpeg.wait().expect("FFmpeg error").success() {
+            panic!("FFmpeg couldn't read the audio in {}", file_location);
+        }
+    }
+
+    // listens again to the audio around words the model wasn't sure of, only letting it hear words on the list - confirms listed words it wasn't sure of, and finds ones it heard as something else
+    fn grammar_pass(
+        file_location: &str,
+        model: &Model,
+        words: &mut Vec<Word>,
+        expletives: &crate::Expletives,
+        below: f32,
+    ) {
+        // merges the windows around each unsure word, so no audio is listened to twice
+        let mut windows: Vec<(usize, usize)> = Vec::new();
+        for word in words.iter().filter(|w| w.confidence.unwrap_or(1.) < below) {
+            let start = ((word.start - GRAMMAR_WINDOW).max(0.) * 16000.) as usize;
+            let end = ((word.end + GRAMMAR_WINDOW) * 16000.) as usize;
+
+            match windows.last_mut() {
+                Some(last) if start <= last.1 => last.1 = last.1.max(end),
+                _ => windows.push((start, end)),
+            }
+        }
+
+        if windows.is_empty() {
+            return;
+        }
+
+        // only the windows are kept, so this doesn't need the whole file in memory either
+        let mut window_samples: Vec<Vec<i16>> = vec![Vec::new(); windows.len()];
+        let mut position = 0;
+        VoskLocal::read_audio(file_location, |samples| {
+            let block_end = position + samples.len();
+
+            for (i, (start, end)) in windows.iter().enumerate() {
+                let from = (*start).max(position);
+                let to = (*end).min(block_end);
+
+                if from < to {
+                    window_samples[i].extend_from_slice(&samples[from - position..to - position]);
+                }
+            }
+
+            position = block_end;
+        });
+
+        // anything that isn't on the list comes out as [unk]
+        let mut grammar: Vec<&str> = expletives.keys().map(|e| e.as_str()).collect();
+        grammar.push("[unk]");
+
+        for ((start, _), samples) in windows.iter().zip(window_samples) {
+            // a new recognizer for each window, since vosk's timestamps would keep counting from the windows before
+            let mut recognizer = Recognizer::new_with_grammar(model, 16000., &grammar)
+                .expect("Could not create grammar recognizer");
+            recognizer.set_words(true);
+
+            recognizer.accept_waveform(&samples);
+            let result = recognizer
+                .final_result()
+                .single()
+                .expect("Error in outputting result");
+
+            let offset = *start as f32 / 16000.;
+
+            for heard in result.result {
+                if !expletives.contains_key(heard.word) {
+                    continue;
+                }
+
+                let mut heard = Word::from(heard);
+                heard.start += offset;
+                heard.end += offset;
+
+                // whoever said the words around it said it too
+                heard.speaker = words
+                    .iter()
+                    .find(|w| w.start < heard.end && heard.start < w.end)
+                    .and_then(|w| w.speaker.clone());
+
+                // a word the first pass already had is confirmed, anything else is new
+                match words
+                    .iter_mut()
+                    .find(|w| w.word == heard.word && w.start < heard.end && heard.start < w.end)
+                {
+                    Some(word) => {
+                        word.confidence = match (word.confidence, heard.confidence) {
+                            (Some(a), Some(b)) => Some(a.max(b)),
+                            (a, b) => a.or(b),
+                        }
+                    }
+                    None => words.push(heard),
+                }
+            }
+        }
+
+        words.sort_by(|a, b| a.start.total_cmp(&b.start));
     }
 
-    // the function for each of the threads to run
+    // the function for each of the threads to run - takes chunks off the queue until there aren't any left, and gives back every utterance it heard in each one, along with the voice of whoever said it if there's a speaker model
     fn split_threads(
-        temp_dir_name: String,
-        recognizer: &mut Recognizer,
-        samples: Vec<i16>,
+        new_recognizer: impl Fn() -> Recognizer,
+        queue: Arc<Mutex<Receiver<SpeechChunk>>>,
         thread_name: &str,
-    ) {
-        // Feed the model the sound file.
-        recognizer.accept_waveform(&samples);
-
-        // binds a temporary value so I can keep the results
-        let binding = recognizer
-            .final_result()
-            .single()
-            .expect("Error in outputting result");
-        let curses = binding.result;
-
-        // makes the temp json file name
-        #[cfg(unix)]
-        let name = format!("{}/{}.json", temp_dir_name, thread_name);
-
-        #[cfg(windows)]
-        let name = format!("{}\\{}.json", temp_dir_name, thread_name);
-
-        // writes it to file
-        fs::write(name, json!(curses).to_string()).expect(&format!(
-            "Error outputting thread {} json to file",
-            thread_name
-        ));
+        alternatives: Option<(f32, crate::Expletives)>,
+    ) -> Vec<ChunkResult> {
+        let mut curses = Vec::new();
+
+        loop {
+            // the lock is let go of as soon as the next chunk is taken
+            let next = queue.lock().expect("Error locking chunk queue").recv();
+            let chunk = match next {
+                Ok(chunk) => chunk,
+                Err(_) => break,
+            };
+
+            // each recognizer thinks its chunk is at the beginning of the audio, so the timestamps are moved to where the chunk is
+            let offset = chunk.start as f32 / 16000.;
+            let core_start = chunk.core_start as f32 / 16000.;
+            let core_end = chunk.core_end as f32 / 16000.;
+
+            // every chunk gets a fresh recognizer - vosk keeps counting time from everything a recognizer's already heard, even after final_result
+            let mut recognizer = new_recognizer();
+            let utterances = VoskLocal::recognize(&mut recognizer, &chunk.samples, &alternatives);
+            let mut kept = Vec::new();
+
+            for (voice, words) in utterances {
+                let mut kept_words = Vec::new();
+
+                for mut word in words {
+                    word.start += offset;
+                    word.end += offset;
+
+                    // words in the overlap are heard by both chunks - each one only keeps the ones centered in its own core
+                    let middle = (word.start + word.end) / 2.;
+                    if middle >= core_start && middle < core_end {
+                        kept_words.push(word);
+                    }
+                }
+
+                if !kept_words.is_empty() {
+                    kept.push((voice, kept_words));
+                }
+            }
+
+            // the chunk's start goes along with its utterances, so the speakers can be worked out in order once every thread's done
+            curses.push((chunk.start, kept));
+        }
 
         #[cfg(debug_assertions)]
         println!("Thread {} done!", thread_name);
+
+        curses
     }
 
-    // cleans up the temp files that were generated
-    fn clean_up(&self) {
-        fs::remove_dir_all(self.temp_dir_name.clone()).expect("Error removing temp dir");
+    // runs one chunk through a recognizer that hasn't heard anything yet - it's fed a bit at a time, so every utterance comes back on its own with its own voice or alternatives, instead of the whole chunk sharing one
+    fn recognize(
+        recognizer: &mut Recognizer,
+        samples: &[i16],
+        alternatives: &Option<(f32, crate::Expletives)>,
+    ) -> Vec<Utterance> {
+        let mut utterances = Vec::new();
+
+        for piece in samples.chunks(PIECE) {
+            if recognizer.accept_waveform(piece) == DecodingState::Finalized {
+                utterances.push(VoskLocal::utterance(recognizer.result(), alternatives));
+            }
+        }
+        utterances.push(VoskLocal::utterance(
+            recognizer.final_result(),
+            alternatives,
+        ));
+
+        utterances
     }
 
-    // makes the temp dir if it isn't there already
-    fn make_temp_dir(temp_dir_name: String) {
-        // gets the absolute path to here
-        let here = fs::canonicalize("./").expect("Error in canonicalizing temp path");
+    // the words in one of vosk's results, and the voice they were said in
+    fn utterance(
+        result: CompleteResult,
+        alternatives: &Option<(f32, crate::Expletives)>,
+    ) -> Utterance {
+        match alternatives {
+            None => {
+                let result = result.single().expect("Error in outputting result");
+                (
+                    result.speaker_info.map(|s| s.vector),
+                    result.result.into_iter().map(Word::from).collect(),
+                )
+            }
+            // the speaker model's voices only come with single results
+            Some((threshold, expletives)) => (
+                None,
+                VoskLocal::alternative_words(
+                    result.multiple().expect("Error in outputting result"),
+                    *threshold,
+                    expletives,
+                ),
+            ),
+        }
+    }
 
-        let mut without_dot = temp_dir_name.clone();
-        without_dot.remove(0);
+    // the best alternative's words, plus any listed words from the others that are likely enough
+    fn alternative_words(
+        result: CompleteResultMultiple,
+        threshold: f32,
+        expletives: &crate::Expletives,
+    ) -> Vec<Word> {
+        let alternatives = result.alternatives;
+
+        // vosk's confidences for alternatives are log scores, so they're turned into probabilities that add up to 1
+        let max = alternatives
+            .iter()
+            .map(|a| a.confidence)
+            .fold(f32::MIN, f32::max);
+        let total: f32 = alternatives
+            .iter()
+            .map(|a| (a.confidence - max).exp())
+            .sum();
+
+        let mut words: Vec<Word> = Vec::new();
+
+        // vosk gives the alternatives best first
+        for (i, alternative) in alternatives.iter().enumerate() {
+            let probability = (alternative.confidence - max).exp() / total;
+
+            if i > 0 && probability < threshold {
+                continue;
+            }
 
-        // add the temp dir as a string
-        let temp_dir_location = here.display().to_string() + &without_dot;
+            for w in &alternative.result {
+                // everything from the best guess, but only listed words the best guess didn't already have from the others
+                if i > 0
+                    && (!expletives.contains_key(w.word)
+                        || words
+                            .iter()
+                            .any(|x| x.word == w.word && x.start < w.end && w.start < x.end))
+                {
+                    continue;
+                }
 
-        // if it isn't there already
-        if !std::path::Path::new(&temp_dir_location).exists() {
-            // make it
-            fs::create_dir(temp_dir_location).expect("Error in creating temp dir");
+                words.push(Word {
+                    word: w.word.to_string(),
+                    start: w.start,
+                    end: w.end,
+                    // the probability is for the whole utterance, not the word - passing it on as the word's confidence would have --censor-above drop swears the model was sure of, and send nearly everything to the grammar pass
+                    confidence: None,
+                    speaker: None,
+                });
+            }
         }
+
+        words
     }
 
-    pub fn get_model(model: &str, model_path: String) {
+    pub fn get_model(model: &str, model_path: String, sha256: Option<&str>) {
         let url = match model {
             "small" => "https://alphacephei.com/vosk/models/vosk-model-small-en-us-0.15.zip",
             "medium" => "https://alphacephei.com/vosk/models/vosk-model-en-us-0.22-lgraph.zip",
@@ -196,46 +530,270 @@ impl VoskLocal {
             _ => panic!("It should be impossible to get here"),
         };
 
-        let output_dir = Path::new(&model_path);
+        println!("Getting model '{}' at {} ...", model, url);
+
+        // Download the ZIP file
+        let zip_location = VoskLocal::download_file(url)
+            .unwrap_or_else(|e| panic!("Error downloading file {}: {}", url, e));
+
+        // checks the zip is what it's supposed to be - otherwise, it's removed so the next try starts over
+        match sha256 {
+            Some(expected) => {
+                let hash =
+                    VoskLocal::file_sha256(&zip_location).expect("Error hashing downloaded model");
+
+                if !hash.eq_ignore_ascii_case(expected) {
+                    let _ = fs::remove_file(&zip_location);
+                    panic!(
+                        "The downloaded model's SHA-256 is {}, not {} - it's probably corrupt",
+                        hash, expected
+                    );
+                }
+                println!("SHA-256 checks out");
+            }
+            // without a hash to check against, it's checked against the md5 Alpha Cephei lists for it
+            None => {
+                let name = url
+                    .rsplit('/')
+                    .next()
+                    .and_then(|f| f.strip_suffix(".zip"))
+                    .expect("Error getting model name from url");
+
+                match vosk_models::registry_md5(name) {
+                    Ok(Some(expected)) => {
+                        let hash = vosk_models::file_md5(&zip_location)
+                            .expect("Error hashing downloaded model");
+
+                        if hash != expected {
+                            let _ = fs::remove_file(&zip_location);
+                            panic!(
+                                "The downloaded model's MD5 is {}, not {} - it's probably corrupt",
+                                hash, expected
+                            );
+                        }
+                        println!("MD5 checks out");
+                    }
+                    Ok(None) => println!(
+                        "{} isn't in the list of models, so it can't be checked - use --sha256 to check it",
+                        name
+                    ),
+                    Err(e) => println!(
+                        "Couldn't get the list of models, so the download can't be checked - use --sha256 to check it: {}",
+                        e
+                    ),
+                }
+            }
+        }
+
+        // Unzip the downloaded file next to the current model, which is only replaced once the new one's ready
+        let staging = VoskLocal::staging_dir(&model_path);
+        VoskLocal::unzip_file(&zip_location, &staging).expect("Error unzipping file");
+        fs::remove_file(&zip_location).expect("Error removing downloaded zip");
+
+        VoskLocal::replace_model(&staging, &model_path, url);
+    }
+
+    // installs a model zip that's already on this machine, for when there's no internet
+    pub fn get_model_from_file(zip_location: &str, model_path: String) {
+        let staging = VoskLocal::staging_dir(&model_path);
+
+        println!("Getting model from {} ...", zip_location);
+
+        VoskLocal::unzip_file(Path::new(zip_location), &staging).expect("Error unzipping file");
+
+        VoskLocal::replace_model(&staging, &model_path, zip_location);
+    }
+
+    // installs a model that's already been extracted somewhere on this machine
+    pub fn get_model_from_dir(dir: &str, model_path: String) {
+        let staging = VoskLocal::staging_dir(&model_path);
+
+        println!("Getting model from {} ...", dir);
+
+        VoskLocal::copy_dir(Path::new(dir), &staging).expect("Error copying model");
+
+        VoskLocal::replace_model(&staging, &model_path, dir);
+    }
+
+    // an empty directory next to the model to put the new one together in - it's on the same drive, so moving it into place later is quick
+    pub fn staging_dir(model_path: &str) -> PathBuf {
+        let model_path = Path::new(model_path);
+        let name = model_path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .expect("Error getting model directory name");
+        let staging = model_path
+            .parent()
+            .expect("Model directory has no parent")
+            .join(format!(".{}.staging", name));
+
+        if staging.exists() {
+            fs::remove_dir_all(&staging).expect("Error removing old staging directory");
+        }
+        fs::create_dir_all(&staging).expect("Error making staging directory");
+
+        staging
+    }
+
+    // checks the model in staging actually works before it takes the current one's place
+    pub fn replace_model(staging: &Path, model_path: &str, source: &str) {
+        // models usually come with everything in a folder named after them
+        let entries: Vec<PathBuf> = fs::read_dir(staging)
+            .expect("Error reading new model")
+            .filter_map(|e| e.ok().map(|e| e.path()))
+            .collect();
+        if entries.len() == 1 && entries[0].is_dir() {
+            VoskLocal::unwrap_model(&entries[0]).expect("Error unwrapping model directories");
+        }
+
+        if !VoskLocal::is_usable_model(staging) {
+            let _ = fs::remove_dir_all(staging);
+            panic!(
+                "{} isn't a Vosk model Vosk can load - the current model hasn't been touched",
+                source
+            );
+        }
+
+        // the current model is moved aside rather than removed, so it can be put back if the new one can't be moved in
+        let output_dir = Path::new(model_path);
+        let old = staging.with_file_name(format!(
+            "{}.old",
+            staging
+                .file_name()
+                .and_then(|n| n.to_str())
+                .expect("Error getting staging directory name")
+                .trim_end_matches(".staging")
+        ));
+        if old.exists() {
+            fs::remove_dir_all(&old).expect("Error removing old model");
+        }
 
         if output_dir.exists() {
-            std::fs::remove_dir_all(output_dir).expect("Error removing current model");
+            fs::rename(output_dir, &old).expect("Error moving current model aside");
         }
 
-        println!("Getting model '{}' at {} ...", model, url);
+        if let Err(e) = fs::rename(staging, output_dir) {
+            if old.exists() {
+                fs::rename(&old, output_dir).expect("Error putting current model back");
+            }
+            panic!("Error moving new model into place: {}", e);
+        }
 
-        // Download the ZIP file
-        let zip_data =
-            VoskLocal::download_file(url).expect(&format!("Error downloading file: {}", url));
+        if old.exists() {
+            fs::remove_dir_all(&old).expect("Error removing old model");
+        }
+
+        println!("Done installing model");
+    }
+
+    // whether vosk can load the model at model_dir
+    pub fn is_usable_model(model_dir: &Path) -> bool {
+        match model_dir.to_str() {
+            Some(model_dir) => Model::new(model_dir).is_some(),
+            None => false,
+        }
+    }
+
+    fn copy_dir(from: &Path, to: &Path) -> io::Result<()> {
+        fs::create_dir_all(to)?;
 
-        // Unzip the downloaded file
-        VoskLocal::unzip_file(&zip_data, output_dir).expect("Error unzipping file");
+        for entry in fs::read_dir(from)? {
+            let entry = entry?;
+            let path = entry.path();
 
-        let mut entry =
-            std::fs::read_dir(output_dir).expect("error getting downloaded model directory");
+            if path.is_dir() {
+                VoskLocal::copy_dir(&path, &to.join(entry.file_name()))?;
+            } else {
+                fs::copy(&path, to.join(entry.file_name()))?;
+            }
+        }
+
+        Ok(())
+    }
 
-        let model_dir = entry.next().unwrap().unwrap();
+    // downloads the file into ~/.project-soap/downloads, picking up where the last try left off if it didn't finish, and gives back where it put it
+    pub fn download_file(url: &str) -> Result<PathBuf, Box<dyn std::error::Error>> {
+        let downloads = home_dir()
+            .expect("Error getting user's home directory")
+            .join(".project-soap")
+            .join("downloads");
 
-        VoskLocal::unwrap_model(&model_dir.path()).expect("Error unwrapping model directories");
+        VoskLocal::download_file_to(url, &downloads)
     }
 
-    fn download_file(url: &str) -> Result<Vec<u8>, reqwest::Error> {
+    fn download_file_to(
+        url: &str,
+        downloads: &Path,
+    ) -> Result<PathBuf, Box<dyn std::error::Error>> {
+        fs::create_dir_all(downloads)?;
+
+        let file_name = url.rsplit('/').next().unwrap_or("model.zip");
+        let location = downloads.join(file_name);
+        // the download goes here until it's done, so a half downloaded file is never mistaken for a whole one
+        let part_location = downloads.join(format!("{}.part", file_name));
+
+        // no overall timeout, since big models can take a long time - only connecting can time out
         let client = Client::builder()
-            .timeout(std::time::Duration::from_secs(600)) // Set a timeout of 10 minutes
+            .timeout(None::<Duration>)
+            .connect_timeout(Duration::from_secs(30))
             .build()?;
 
-        let mut response = client.get(url).send()?;
-        let mut content = Vec::new();
-        response.copy_to(&mut content)?;
+        let already_downloaded = fs::metadata(&part_location).map(|m| m.len()).unwrap_or(0);
+
+        let mut request = client.get(url);
+        if already_downloaded > 0 {
+            request = request.header(RANGE, format!("bytes={}-", already_downloaded));
+        }
+        let response = request.send()?;
+
+        // the server says there's nothing left to send
+        if response.status() == StatusCode::RANGE_NOT_SATISFIABLE {
+            fs::rename(&part_location, &location)?;
+            println!("Done downloading model");
+            return Ok(location);
+        }
+        let response = response.error_for_status()?;
+
+        // the server can also ignore the range and send the whole thing again
+        let (mut part, start) = if response.status() == StatusCode::PARTIAL_CONTENT {
+            (
+                OpenOptions::new().append(true).open(&part_location)?,
+                already_downloaded,
+            )
+        } else {
+            (File::create(&part_location)?, 0)
+        };
+
+        let progress = ProgressBar::new(start + response.content_length().unwrap_or(0));
+        progress.set_style(
+            ProgressStyle::with_template(
+                "{bar:40} {bytes}/{total_bytes} ({bytes_per_sec}, {eta} left)",
+            )
+            .expect("Error making progress bar"),
+        );
+        progress.set_position(start);
+
+        io::copy(&mut progress.wrap_read(response), &mut part)?;
+        progress.finish();
 
+        fs::rename(&part_location, &location)?;
         println!("Done downloading model");
 
-        Ok(content)
+        Ok(location)
+    }
+
+    // the hex sha256 of the file, read a bit at a time so big models don't have to fit in memory
+    pub fn file_sha256(file_location: &Path) -> io::Result<String> {
+        let mut hasher = Sha256::new();
+        io::copy(&mut File::open(file_location)?, &mut hasher)?;
+        Ok(format!("{:x}", hasher.finalize()))
     }
 
-    fn unzip_file(data: &[u8], output_dir: &Path) -> Result<(), Box<dyn std::error::Error>> {
-        let reader = Cursor::new(data);
-        let mut zip = ZipArchive::new(reader)?;
+    pub fn unzip_file(
+        zip_location: &Path,
+        output_dir: &Path,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let mut zip = ZipArchive::new(File::open(zip_location)?)?;
 
         for i in 0..zip.len() {
             let mut file = zip.by_index(i)?;
@@ -260,7 +818,7 @@ impl VoskLocal {
         Ok(())
     }
 
-    fn unwrap_model(model_dir: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    pub fn unwrap_model(model_dir: &Path) -> Result<(), Box<dyn std::error::Error>> {
         let parent_folder = model_dir.parent().expect("Folder has no parent");
 
         // Read the contents of the folder
@@ -285,102 +843,147 @@ impl VoskLocal {
 }
 
 impl Cleaner for VoskLocal {
-    fn transcribe(&mut self) -> Vec<crate::backends::Word> {
-        self.preprocess_audio();
-
-        // Load the Vosk model
-        let model = Model::new(self.model_location.clone()).expect("Could not create model");
-
-        // Open the WAV file
-        let mut reader = hound::WavReader::open(self.preprocessed_file_location.clone())
-            .expect("Could not open WAV file");
-
-        // Check if audio file is mono PCM
-        if reader.spec().channels != 1 || reader.spec().sample_format != hound::SampleFormat::Int {
-            panic!("Audio file must be WAV format mono PCM.");
+    fn transcribe(&mut self, file_location: &str) -> Vec<crate::backends::Word> {
+        // Load the Vosk model, if it hasn't been already
+        let model = self
+            .model
+            .get_or_insert_with(|| VoskLocal::shared_model(&self.model_location));
+
+        // Load the speaker model too, if there is one and it hasn't been already
+        if self.speaker_model.is_none() {
+            self.speaker_model = self.speaker_model_location.as_ref().map(|location| {
+                Arc::new(SpeakerModel::new(location).expect("Could not create speaker model"))
+            });
         }
 
-        // Get the samples from the WAV file
-        let samples = reader
-            .samples()
-            .collect::<hound::Result<Vec<i16>>>()
-            .expect("Could not read WAV file");
+        // alternatives are only kept when they have something on the list in them
+        let expletives = crate::load_expletives();
 
-        // splits the audio into thread_number chunks
-        let mut sample_chunks = samples.chunks(samples.len() / self.thread_number);
+        // only a few chunks are kept waiting for the threads at once, so reading the audio can't get too far ahead of them
+        let (sender, receiver) = mpsc::sync_channel(self.thread_number * 2);
+        let queue = Arc::new(Mutex::new(receiver));
 
         // a vector to make it so we can wait for all the threads to finish before making the filters for ffmpeg
-        let mut threads: Vec<JoinHandle<()>> = Vec::new();
+        let mut threads: Vec<JoinHandle<Vec<ChunkResult>>> = Vec::new();
 
         for i in 0..self.thread_number {
-            //make and configure a new Recognizer
-            let mut recognizer =
-                Recognizer::new(&model, 16000 as f32).expect("Could not create recognizer");
-            recognizer.set_words(true);
+            // the models are shared, so making a recognizer for each chunk is cheap
+            let model = Arc::clone(model);
+            let speaker_model = self.speaker_model.clone();
+            let max_alternatives = self.alternatives;
+
+            let new_recognizer = move || {
+                //make and configure a new Recognizer
+                let mut recognizer =
+                    Recognizer::new(&model, 16000 as f32).expect("Could not create recognizer");
+                recognizer.set_words(true);
+                if max_alternatives > 0 {
+                    recognizer.set_max_alternatives(max_alternatives);
+                }
+                if let Some(speaker_model) = &speaker_model {
+                    recognizer.set_speaker_model(speaker_model);
+                }
+                recognizer
+            };
 
-            // get the next sample chunk
-            let sample_chunk = sample_chunks.next().unwrap().to_vec();
+            // copy the queue to send to the threads
+            let queue = Arc::clone(&queue);
 
-            // copy the file name to send to the threads
-            let temp_dir_name_copy = self.temp_dir_name.clone();
+            let alternatives = if self.alternatives > 0 {
+                Some((self.alternative_threshold, expletives.clone()))
+            } else {
+                None
+            };
 
             // actually split off the thread
             let thread = thread::spawn(move || {
-                VoskLocal::split_threads(
-                    temp_dir_name_copy,
-                    &mut recognizer,
-                    sample_chunk,
-                    &format!("{:?}", i),
-                )
+                VoskLocal::split_threads(new_recognizer, queue, &format!("{:?}", i), alternatives)
             });
 
             // add the new thread's JoinHandle to the vec so we can wait for it later
             threads.push(thread);
         }
 
-        // later is now
+        // hands out chunks to the threads as they're finished
+        let mut chunker = audio::SpeechChunker::new(
+            16000,
+            self.silence_threshold,
+            MAX_CHUNK,
+            (self.overlap * 16000.) as usize,
+        );
+
+        VoskLocal::read_audio(file_location, |samples| {
+            for chunk in chunker.push(samples) {
+                sender.send(chunk).expect("Error queueing chunk");
+            }
+        });
+
+        for chunk in chunker.finish() {
+            sender.send(chunk).expect("Error queueing chunk");
+        }
+        // dropping the sender lets the threads know when the queue's empty
+        drop(sender);
+
+        // later is now - the threads already put the timestamps where they are in the file
+        let mut chunk_results: Vec<ChunkResult> = Vec::new();
         for thread in threads {
-            thread.join().expect("Error joining threads");
+            chunk_results.extend(thread.join().expect("Error joining threads"));
         }
 
-        // initializes a vector for the list of transcribed words and the temp json files' contents
+        // voices are matched up in the order they were heard, so the first person to talk is always SPEAKER_00
+        chunk_results.sort_by_key(|(start, _)| *start);
+        let mut voices: Vec<Vec<f32>> = Vec::new();
+
         let mut times_in: Vec<crate::backends::Word> = Vec::new();
-        let mut file_contents: Vec<String> = vec![String::new(); self.thread_number]; // we need this out here because having a temp var in the loop wouldn't have a long enough lifetime
-
-        // we use the counter to keep track of the thread number we're currently on - prob not the best way to do that
-        let mut counter = 0;
-        let offset: f32 = (samples.len() as f32) / (self.thread_number as f32);
-        for i in file_contents.iter_mut() {
-            #[cfg(unix)]
-            let json_name = format!("{}/{}.json", self.temp_dir_name, counter);
-
-            #[cfg(windows)]
-            let json_name = format!("{}\\{}.json", self.temp_dir_name, counter);
-
-            // read the temp json file into i - the format! call probably isn't the best way to do this
-            *i = fs::read_to_string(json_name.clone())
-                .expect(&format!("Error opening json file at {}", json_name));
-
-            // deserializes the json file into a Vec<vosk::Word>
-            let mut json: Vec<vosk::Word> =
-                serde_json::from_str(i).expect("Error in deserializing json");
-
-            // offsets the word timestamps - each recognizer thinks it's at the beginning of the audio, so without this, there's just a bunch of holes at the beginning of the input file
-            for word in json.iter_mut() {
-                word.start += (offset / 16000.) * counter as f32;
-                word.end += (offset / 16000.) * counter as f32;
-                times_in.push(crate::backends::Word::from(word.clone()));
+        for (voice, words) in chunk_results.into_iter().flat_map(|(_, u)| u) {
+            let speaker =
+                voice.map(|v| format!("SPEAKER_{:02}", VoskLocal::which_speaker(&mut voices, v)));
+
+            for mut word in words {
+                word.speaker = speaker.clone();
+                times_in.push(word);
             }
+        }
+
+        // the threads took chunks in whatever order they got to them
+        times_in.sort_by(|a, b| a.start.total_cmp(&b.start));
 
-            counter += 1;
+        if let Some(below) = self.grammar_below {
+            VoskLocal::grammar_pass(file_location, model, &mut times_in, &expletives, below);
         }
 
-        self.clean_up();
         times_in
     }
+
+    fn describe(&self) -> String {
+        // the audio is split at silences, so the thread number doesn't change the output
+        format!(
+            "vosk-local --model {} --model-fingerprint {} --silence-threshold {} --overlap {} --alternatives {} --alternative-threshold {}{}{}{}",
+            self.model_location,
+            self.model_fingerprint,
+            self.silence_threshold,
+            self.overlap,
+            self.alternatives,
+            self.alternative_threshold,
+            match self.grammar_below {
+                Some(below) => format!(" --grammar-pass --grammar-below {}", below),
+                None => String::new(),
+            },
+            match &self.speaker_model_location {
+                Some(location) => format!(" --speaker-model {}", location),
+                None => String::new(),
+            },
+            // alternatives and the grammar pass only keep extra words that are on the list, so the transcript depends on it
+            if self.alternatives > 0 || self.grammar_below.is_some() {
+                format!(" --list {}", crate::list_hash())
+            } else {
+                String::new()
+            }
+        )
+    }
 }
 
-#[derive(clap::Subcommand, PartialEq)]
+#[derive(clap::Subcommand, PartialEq, Clone)]
 pub enum VoskLocalCommands {
     /// Download a Vosk model from the web
     GetModel {
@@ -395,6 +998,24 @@ pub enum VoskLocalCommands {
         /// vosk-model-en-us-0.22 - 1.8Gb - big, even more accurate, requires a lot of RAM
         #[arg(long, group = "model")]
         large: bool,
+
+        /// Install a model zip that's already been downloaded, instead of getting one from the web
+        #[arg(long, group = "model")]
+        from_file: Option<String>,
+
+        /// Install a model that's already been downloaded and extracted into this directory, instead of getting one from the web
+        #[arg(long, group = "model")]
+        from_dir: Option<String>,
+
+        /// The SHA-256 the downloaded model zip should have - it isn't installed if it doesn't match
+        #[arg(long)]
+        sha256: Option<String>,
+    },
+
+    /// List, install, remove, verify, and pick the default of the models in ~/.project-soap/models/vosk
+    Models {
+        #[command(subcommand)]
+        command: vosk_models::ModelsCommands,
     },
 }
 
@@ -403,7 +1024,153 @@ impl From<vosk::Word<'_>> for crate::backends::Word {
         let word = value.word.to_string();
         let start = value.start;
         let end = value.end;
+        let confidence = Some(value.conf);
+
+        crate::backends::Word {
+            word,
+            start,
+            end,
+            confidence,
+            speaker: None,
+        }
+    }
+}
+
+// how alike two voices are, from -1 to 1
+fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
+    let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    let length = |v: &[f32]| v.iter().map(|x| x * x).sum::<f32>().sqrt();
+
+    dot / (length(a) * length(b)).max(f32::EPSILON)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+
+    const MODEL: &[u8] = b"pretend this is a whole vosk model zip";
+
+    // serves one request with the given response, and gives back the range the request asked for
+    fn serve_once(response: Vec<u8>) -> (String, JoinHandle<Option<String>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").expect("Error starting test server");
+        let url = format!(
+            "http://{}/model.zip",
+            listener
+                .local_addr()
+                .expect("Error getting test server address")
+        );
+
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().expect("Error accepting connection");
+            let mut range = None;
+
+            let mut reader = BufReader::new(stream.try_clone().expect("Error cloning stream"));
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).expect("Error reading request");
+                if line.trim().is_empty() {
+                    break;
+                }
+                if let Some((name, value)) = line.split_once(':') {
+                    if name.eq_ignore_ascii_case("range") {
+                        range = Some(value.trim().to_string());
+                    }
+                }
+            }
+
+            stream.write_all(&response).expect("Error writing response");
+            range
+        });
+
+        (url, server)
+    }
+
+    fn response(status: &str, headers: &str, body: &[u8]) -> Vec<u8> {
+        let mut response = format!(
+            "HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n{}\r\n",
+            status,
+            body.len(),
+            headers
+        )
+        .into_bytes();
+        response.extend_from_slice(body);
+        response
+    }
+
+    // a fresh downloads directory with a partial download already in it
+    fn downloads_with_part(test: &str, part: &[u8]) -> PathBuf {
+        let downloads =
+            std::env::temp_dir().join(format!("project-soap-{}-{}", test, std::process::id()));
+        let _ = fs::remove_dir_all(&downloads);
+        fs::create_dir_all(&downloads).expect("Error making test directory");
+        fs::write(downloads.join("model.zip.part"), part).expect("Error writing partial download");
+
+        downloads
+    }
+
+    #[test]
+    fn download_resumes_from_partial_content() {
+        let downloads = downloads_with_part("resume", &MODEL[..10]);
+        let (url, server) = serve_once(response(
+            "206 Partial Content",
+            &format!(
+                "Content-Range: bytes 10-{}/{}\r\n",
+                MODEL.len() - 1,
+                MODEL.len()
+            ),
+            &MODEL[10..],
+        ));
+
+        let location = VoskLocal::download_file_to(&url, &downloads).expect("Error downloading");
+
+        assert_eq!(server.join().unwrap(), Some(String::from("bytes=10-")));
+        assert_eq!(fs::read(&location).unwrap(), MODEL);
+        assert!(!downloads.join("model.zip.part").exists());
+        let _ = fs::remove_dir_all(&downloads);
+    }
+
+    #[test]
+    fn download_starts_over_when_the_range_is_ignored() {
+        let downloads = downloads_with_part("ignored-range", b"something else entirely");
+        let (url, server) = serve_once(response("200 OK", "", MODEL));
+
+        let location = VoskLocal::download_file_to(&url, &downloads).expect("Error downloading");
+
+        assert!(server.join().unwrap().is_some());
+        assert_eq!(fs::read(&location).unwrap(), MODEL);
+        let _ = fs::remove_dir_all(&downloads);
+    }
+
+    #[test]
+    fn download_is_done_when_the_range_is_past_the_end() {
+        let downloads = downloads_with_part("finished", MODEL);
+        let (url, server) = serve_once(response(
+            "416 Range Not Satisfiable",
+            &format!("Content-Range: bytes */{}\r\n", MODEL.len()),
+            b"",
+        ));
+
+        let location = VoskLocal::download_file_to(&url, &downloads).expect("Error downloading");
+
+        assert_eq!(
+            server.join().unwrap(),
+            Some(format!("bytes={}-", MODEL.len()))
+        );
+        assert_eq!(fs::read(&location).unwrap(), MODEL);
+        let _ = fs::remove_dir_all(&downloads);
+    }
+
+    #[test]
+    fn download_starts_fresh_without_a_partial_download() {
+        let downloads = downloads_with_part("fresh", b"");
+        let (url, server) = serve_once(response("200 OK", "", MODEL));
+
+        let location = VoskLocal::download_file_to(&url, &downloads).expect("Error downloading");
 
-        crate::backends::Word { word, start, end }
+        assert_eq!(server.join().unwrap(), None);
+        assert_eq!(fs::read(&location).unwrap(), MODEL);
+        let _ = fs::remove_dir_all(&downloads);
     }
 }
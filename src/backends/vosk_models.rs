@@ -0,0 +1,516 @@
+use dirs::home_dir;
+use md5::Md5;
+use reqwest::blocking::Client;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::BTreeMap,
+    fs::{self, File},
+    io,
+    path::{Component, Path, PathBuf},
+};
+
+use crate::backends::vosk_local::VoskLocal;
+
+// every model Alpha Cephei has, in every language
+const REGISTRY_URL: &str = "https://alphacephei.com/vosk/models/model-list.json";
+
+#[derive(clap::Subcommand, PartialEq, Clone)]
+pub enum ModelsCommands {
+    /// List the installed models
+    List {
+        /// List the models that can be installed instead
+        #[arg(long)]
+        available: bool,
+
+        /// Only list models for this language, like "en-us" or "de"
+        #[arg(long)]
+        language: Option<String>,
+    },
+
+    /// Install a model from the registry, or from a zip file
+    Install {
+        /// The model's name, like "vosk-model-small-de-0.15" - see "models list --available" for the rest
+        #[arg(required_unless_present = "from_file")]
+        name: Option<String>,
+
+        /// Install from this zip file instead of downloading it
+        #[arg(long)]
+        from_file: Option<String>,
+    },
+
+    /// Remove an installed model
+    Remove {
+        /// The model's name
+        name: String,
+    },
+
+    /// Check an installed model's files haven't changed since it was installed - checks all of them if no name is given
+    Verify {
+        /// The model's name
+        name: Option<String>,
+    },
+
+    /// Use this installed model when --model isn't given
+    Default {
+        /// The model's name
+        name: String,
+    },
+}
+
+// a model in the registry - there's more in there, but this is all we need
+#[derive(Deserialize)]
+struct RegistryModel {
+    name: String,
+    lang: String,
+    md5: String,
+    size: u64,
+    url: String,
+    // "true" or "false", as a string
+    #[serde(default)]
+    obsolete: String,
+}
+
+// written next to each installed model, so it can be listed and verified later
+#[derive(Serialize, Deserialize)]
+struct Manifest {
+    name: String,
+    lang: String,
+    // the url or file it was installed from
+    source: String,
+    // the sha256 of every file in the model, by its path inside the model
+    files: BTreeMap<String, String>,
+}
+
+pub fn run(command: &ModelsCommands) {
+    match command {
+        ModelsCommands::List {
+            available,
+            language,
+        } => {
+            if *available {
+                list_available(language.as_deref());
+            } else {
+                list_installed(language.as_deref());
+            }
+        }
+        ModelsCommands::Install { name, from_file } => {
+            install(name.as_deref(), from_file.as_deref())
+        }
+        ModelsCommands::Remove { name } => remove(name),
+        ModelsCommands::Verify { name } => verify(name.as_deref()),
+        ModelsCommands::Default { name } => set_default(name),
+    }
+}
+
+// where installed models live - ~/.project-soap/models/vosk
+pub fn models_dir() -> PathBuf {
+    home_dir()
+        .expect("Error getting user's home directory")
+        .join(".project-soap")
+        .join("models")
+        .join("vosk")
+}
+
+// where the named model is installed, if it is
+pub fn installed_location(name: &str) -> Option<String> {
+    if !is_model_name(name) {
+        return None;
+    }
+
+    let location = models_dir().join(name);
+    if !location.is_dir() {
+        return None;
+    }
+
+    location.to_str().map(|l| l.to_string())
+}
+
+// a name has to be just one directory inside the models directory - anything else, like "/" or "x/../..", would reach outside it
+fn is_model_name(name: &str) -> bool {
+    let mut components = Path::new(name).components();
+
+    // partial installs start with a dot
+    !name.starts_with('.')
+        && matches!(components.next(), Some(Component::Normal(_)))
+        && components.next().is_none()
+}
+
+// where the model set with "models default" is installed, if one's been set
+pub fn default_location() -> Option<String> {
+    let name = fs::read_to_string(models_dir().join("default")).ok()?;
+    installed_location(name.trim())
+}
+
+fn list_installed(language: Option<&str>) {
+    let default = fs::read_to_string(models_dir().join("default")).unwrap_or_default();
+    let mut found = false;
+
+    for name in installed_names() {
+        let lang = read_manifest(&name)
+            .map(|m| m.lang)
+            .unwrap_or_else(|| String::from("unknown"));
+
+        if !matches_language(&lang, language) {
+            continue;
+        }
+
+        found = true;
+        println!(
+            "{}  {}  {}{}",
+            name,
+            lang,
+            size_text(dir_size(&models_dir().join(&name))),
+            if default.trim() == name {
+                "  (default)"
+            } else {
+                ""
+            }
+        );
+    }
+
+    if !found {
+        println!("No models installed - see \"models list --available\" for ones to install");
+    }
+}
+
+fn list_available(language: Option<&str>) {
+    let registry = registry().expect("Error getting the list of models");
+
+    for model in registry
+        .iter()
+        .filter(|m| m.obsolete != "true" && matches_language(&m.lang, language))
+    {
+        println!("{}  {}  {}", model.name, model.lang, size_text(model.size));
+    }
+}
+
+fn install(name: Option<&str>, from_file: Option<&str>) {
+    // a zip file is named after the model inside it, unless we're told otherwise
+    let name = match (name, from_file) {
+        (Some(name), _) => name.to_string(),
+        (None, Some(file)) => Path::new(file)
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .expect("Error getting file name")
+            .to_string(),
+        (None, None) => panic!("It should be impossible to get here"),
+    };
+
+    if !is_model_name(&name) {
+        panic!("'{}' isn't a model name", name);
+    }
+
+    let location = models_dir().join(&name);
+    if location.exists() {
+        println!(
+            "{} is already installed - remove it first to install it again",
+            name
+        );
+        return;
+    }
+
+    // a zip file can be installed without the registry, but it's checked against it if it's there
+    let entry = match registry() {
+        Ok(registry) => registry.into_iter().find(|m| m.name == name),
+        Err(e) if from_file.is_some() => {
+            println!(
+                "Couldn't get the list of models, so the zip won't be checked: {}",
+                e
+            );
+            None
+        }
+        Err(e) => panic!("Error getting the list of models: {}", e),
+    };
+
+    let (zip_location, source) = match from_file {
+        Some(file) => (PathBuf::from(file), file.to_string()),
+        None => {
+            let entry = entry.as_ref().unwrap_or_else(|| {
+                panic!(
+                    "There's no model called {} - see \"models list --available\"",
+                    name
+                )
+            });
+
+            println!("Getting model '{}' at {} ...", name, entry.url);
+            (
+                VoskLocal::download_file(&entry.url)
+                    .unwrap_or_else(|e| panic!("Error downloading file: {}", e)),
+                entry.url.clone(),
+            )
+        }
+    };
+
+    if let Some(entry) = &entry {
+        if file_md5(&zip_location).expect("Error hashing model zip") != entry.md5 {
+            // a bad download is removed so the next try starts over
+            if from_file.is_none() {
+                let _ = fs::remove_file(&zip_location);
+            }
+            panic!(
+                "The zip for {} doesn't match its checksum - it's probably corrupt or didn't finish downloading",
+                name
+            );
+        }
+    }
+
+    // extracts next to where it goes, so a half extracted model is never mistaken for an installed one
+    let model_path = location
+        .to_str()
+        .expect("Error converting model location to string")
+        .to_string();
+    let staging = VoskLocal::staging_dir(&model_path);
+
+    VoskLocal::unzip_file(&zip_location, &staging).expect("Error unzipping file");
+    if from_file.is_none() {
+        fs::remove_file(&zip_location).expect("Error removing downloaded zip");
+    }
+
+    VoskLocal::replace_model(&staging, &model_path, &source);
+
+    write_manifest(&Manifest {
+        name: name.clone(),
+        lang: entry
+            .map(|e| e.lang)
+            .unwrap_or_else(|| String::from("unknown")),
+        source,
+        files: hash_files(&location),
+    });
+
+    println!("Installed {} at {}", name, location.display());
+}
+
+fn remove(name: &str) {
+    let location = match installed_location(name) {
+        Some(location) => location,
+        None => {
+            println!("{} isn't installed", name);
+            return;
+        }
+    };
+
+    fs::remove_dir_all(&location).expect("Error removing model");
+    let _ = fs::remove_file(manifest_location(name));
+
+    // the default can't be a model that isn't there anymore
+    let default = fs::read_to_string(models_dir().join("default")).unwrap_or_default();
+    if default.trim() == name {
+        fs::remove_file(models_dir().join("default")).expect("Error unsetting default model");
+    }
+
+    println!("Removed {}", name);
+}
+
+fn verify(name: Option<&str>) {
+    let names = match name {
+        Some(name) => vec![name.to_string()],
+        None => installed_names(),
+    };
+
+    for name in names {
+        let location = match installed_location(&name) {
+            Some(location) => PathBuf::from(location),
+            None => {
+                println!("{}: not installed", name);
+                continue;
+            }
+        };
+
+        let manifest = match read_manifest(&name) {
+            Some(manifest) => manifest,
+            None => {
+                println!(
+                    "{}: nothing to check against - it wasn't installed with \"models install\"",
+                    name
+                );
+                continue;
+            }
+        };
+
+        let files = hash_files(&location);
+        let mut problems = Vec::new();
+
+        for (file, hash) in &manifest.files {
+            match files.get(file) {
+                None => problems.push(format!("{} is missing", file)),
+                Some(h) if h != hash => problems.push(format!("{} has changed", file)),
+                Some(_) => {}
+            }
+        }
+
+        if problems.is_empty() {
+            println!("{}: ok", name);
+        } else {
+            println!("{}: {}", name, problems.join(", "));
+        }
+    }
+}
+
+fn set_default(name: &str) {
+    if installed_location(name).is_none() {
+        println!("{} isn't installed", name);
+        return;
+    }
+
+    fs::write(models_dir().join("default"), name).expect("Error setting default model");
+    println!("{} is now the default model", name);
+}
+
+// the md5 the registry has for the named model's zip, if it has the model
+pub fn registry_md5(name: &str) -> Result<Option<String>, String> {
+    Ok(registry()?
+        .into_iter()
+        .find(|m| m.name == name)
+        .map(|m| m.md5))
+}
+
+// the hex md5 of the file, read a bit at a time like file_sha256
+pub fn file_md5(file_location: &Path) -> io::Result<String> {
+    let mut hasher = Md5::new();
+    io::copy(&mut File::open(file_location)?, &mut hasher)?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+fn registry() -> Result<Vec<RegistryModel>, String> {
+    let client = Client::builder()
+        .timeout(std::time::Duration::from_secs(30))
+        .build()
+        .map_err(|e| e.to_string())?;
+
+    let text = client
+        .get(REGISTRY_URL)
+        .send()
+        .and_then(|r| r.error_for_status())
+        .and_then(|r| r.text())
+        .map_err(|e| e.to_string())?;
+
+    serde_json::from_str(&text).map_err(|e| e.to_string())
+}
+
+fn installed_names() -> Vec<String> {
+    let mut names: Vec<String> = match fs::read_dir(models_dir()) {
+        Ok(entries) => entries
+            .filter_map(|e| e.ok())
+            .filter(|e| e.path().is_dir())
+            .filter_map(|e| e.file_name().to_str().map(|n| n.to_string()))
+            // partial installs start with a dot
+            .filter(|n| !n.starts_with('.'))
+            .collect(),
+        Err(_) => Vec::new(),
+    };
+
+    names.sort();
+    names
+}
+
+// "en" matches "en-us" and "en-in", but "en-us" only matches itself
+fn matches_language(lang: &str, language: Option<&str>) -> bool {
+    match language {
+        Some(language) => lang == language || lang.starts_with(&format!("{}-", language)),
+        None => true,
+    }
+}
+
+fn manifest_location(name: &str) -> PathBuf {
+    models_dir().join(format!("{}.json", name))
+}
+
+fn read_manifest(name: &str) -> Option<Manifest> {
+    serde_json::from_str(&fs::read_to_string(manifest_location(name)).ok()?).ok()
+}
+
+fn write_manifest(manifest: &Manifest) {
+    fs::write(
+        manifest_location(&manifest.name),
+        serde_json::to_string_pretty(manifest).expect("Error serializing model manifest"),
+    )
+    .expect("Error writing model manifest");
+}
+
+// hashes every file under dir, by its path inside dir
+fn hash_files(dir: &Path) -> BTreeMap<String, String> {
+    let mut hashes = BTreeMap::new();
+
+    for file in files_in(dir) {
+        let hash = VoskLocal::file_sha256(&file).expect("Error hashing model file");
+        let relative = file
+            .strip_prefix(dir)
+            .expect("Model file outside model directory")
+            .to_string_lossy()
+            .replace('\\', "/");
+
+        hashes.insert(relative, hash);
+    }
+
+    hashes
+}
+
+// a hash that changes when the model at dir is replaced with a different one, even in the same place - its config files, and the size of everything else, since hashing the big files every run would take too long
+pub fn model_fingerprint(dir: &Path) -> String {
+    let mut hasher = Sha256::new();
+
+    let mut files = files_in(dir);
+    files.sort();
+
+    for file in files {
+        let relative = file
+            .strip_prefix(dir)
+            .unwrap_or(&file)
+            .to_string_lossy()
+            .replace('\\', "/");
+        hasher.update(&relative);
+
+        if relative.starts_with("conf/") {
+            hasher.update(fs::read(&file).unwrap_or_default());
+        } else {
+            hasher.update(
+                fs::metadata(&file)
+                    .map(|m| m.len())
+                    .unwrap_or(0)
+                    .to_le_bytes(),
+            );
+        }
+    }
+
+    format!("{:x}", hasher.finalize())
+}
+
+fn dir_size(dir: &Path) -> u64 {
+    files_in(dir)
+        .iter()
+        .filter_map(|f| fs::metadata(f).ok())
+        .map(|m| m.len())
+        .sum()
+}
+
+fn files_in(dir: &Path) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+
+    if let Ok(entries) = fs::read_dir(dir) {
+        for entry in entries.filter_map(|e| e.ok()) {
+            let path = entry.path();
+
+            if path.is_dir() {
+                files.extend(files_in(&path));
+            } else {
+                files.push(path);
+            }
+        }
+    }
+
+    files
+}
+
+fn size_text(bytes: u64) -> String {
+    let units = ["B", "KiB", "MiB", "GiB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+
+    while size >= 1024. && unit < units.len() - 1 {
+        size /= 1024.;
+        unit += 1;
+    }
+
+    format!("{:.1}{}", size, units[unit])
+}
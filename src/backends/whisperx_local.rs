@@ -7,7 +7,6 @@ use crate::{backends::Cleaner, cli};
 use std::{fs::File, io::Read, path::Path, process::Command};
 
 pub struct WhisperXLocal {
-    file_location: String,
     other_options: String,
 }
 impl WhisperXLocal {
@@ -87,8 +86,10 @@ impl WhisperXLocal {
             word: String,
             start: Option<f32>,
             end: Option<f32>,
-            #[allow(dead_code)]
             score: Option<f32>,
+            // only there when WhisperX is run with --diarize
+            #[serde(default)]
+            speaker: Option<String>,
         }
 
         // This is so I can easily convert WhisperX's output to my own internal values
@@ -97,8 +98,16 @@ impl WhisperXLocal {
                 let word = value.word;
                 let start = value.start.ok_or(())?;
                 let end = value.end.ok_or(())?;
-
-                Ok(crate::backends::Word { word, start, end })
+                let confidence = value.score;
+                let speaker = value.speaker;
+
+                Ok(crate::backends::Word {
+                    word,
+                    start,
+                    end,
+                    confidence,
+                    speaker,
+                })
             }
 
             type Error = ();
@@ -132,10 +141,10 @@ impl WhisperXLocal {
         let whisperx_args: String;
 
         match args.backend {
-            cli::Backend::WhisperXLocal {
+            Some(cli::Backend::WhisperXLocal {
                 other_options,
                 setup,
-            } => {
+            }) => {
                 whisperx_args = other_options;
                 s = setup;
             }
@@ -147,17 +156,14 @@ impl WhisperXLocal {
             return None;
         }
 
-        let file_location = args.file_in.expect("no file given");
-
         Some(Box::new(WhisperXLocal {
-            file_location,
             other_options: whisperx_args,
         }))
     }
 }
 impl Cleaner for WhisperXLocal {
     // transcribes the audio
-    fn transcribe(&mut self) -> Vec<super::Word> {
+    fn transcribe(&mut self, file_location: &str) -> Vec<super::Word> {
         let temp_dir = {
             if cfg!(windows) {
                 String::from(
@@ -176,8 +182,7 @@ impl Cleaner for WhisperXLocal {
             }
         };
 
-        let binding = self.file_location.clone();
-        let out_file_name = Path::new(&binding)
+        let out_file_name = Path::new(file_location)
             .file_stem()
             .expect("error getting in file name to find output json")
             .to_str()
@@ -188,7 +193,7 @@ impl Cleaner for WhisperXLocal {
             .arg("run")
             .args(["-n", "whisperx"])
             .arg("whisperx")
-            .arg(self.file_location.clone())
+            .arg(file_location)
             .args(["--output_dir", &temp_dir.clone()])
             .args(["--highlight_words", "True"])
             .args(["--output_format", "json"])
@@ -199,7 +204,7 @@ impl Cleaner for WhisperXLocal {
         #[cfg(windows)]
         let out =
             Command::new("C:\\Users\\squid\\miniconda3\\envs\\whisperx\\Scripts\\whisperx.exe")
-                .arg(self.file_location.clone())
+                .arg(file_location)
                 .args(["--output_dir", &temp_dir.clone()])
                 .args(["--highlight_words", "True"])
                 .args(["--output_format", "json"])
@@ -217,4 +222,8 @@ impl Cleaner for WhisperXLocal {
 
         return self.serialize(String::from(temp_dir + out_file_name + ".json"));
     }
+
+    fn describe(&self) -> String {
+        format!("whisper-x-local --other-options {}", self.other_options)
+    }
 }
@@ -0,0 +1,79 @@
+use dirs::home_dir;
+use sha2::{Digest, Sha256};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+// copies the original somewhere safe before it's overwritten - into ~/.project-soap/backups, or the keep-original directory if one was given
+pub fn back_up(file_location: &str, keep_original: Option<&str>) {
+    let backup = match keep_original {
+        Some(dir) => backup_location_in(Path::new(dir), file_location),
+        None => backup_location(file_location),
+    };
+    fs::create_dir_all(backup.parent().expect("Backup location has no parent"))
+        .expect("Error making backup directory");
+
+    // a file cleaned a second time has already been cleaned once, so the backup from the first time is the real original
+    if backup.exists() {
+        println!(
+            "Keeping the backup of {} already at {}",
+            file_location,
+            backup.display()
+        );
+        return;
+    }
+
+    fs::copy(file_location, &backup).expect("Error backing up original file");
+    println!("Backed up {} to {}", file_location, backup.display());
+}
+
+// puts the backup of the file back where it was - returns false if there isn't one
+pub fn restore(file_location: &str) -> bool {
+    let backup = backup_location(file_location);
+
+    if !backup.exists() {
+        return false;
+    }
+
+    // copies next to the original and renames it over, so the original is never half replaced
+    let temp_location = crate::temp_out_location(file_location);
+    fs::copy(&backup, &temp_location).expect("Error copying backup");
+    fs::rename(&temp_location, file_location).expect("Error restoring backup");
+
+    fs::remove_dir_all(backup.parent().expect("Backup location has no parent"))
+        .expect("Error removing backup");
+
+    true
+}
+
+// each file gets its own directory under ~/.project-soap/backups, named after a hash of its full path
+fn backup_location(file_location: &str) -> PathBuf {
+    backup_location_in(
+        &home_dir()
+            .expect("Error getting user's home directory")
+            .join(".project-soap")
+            .join("backups"),
+        file_location,
+    )
+}
+
+// the directory is named after the whole path, so two files with the same name in a batch never share a backup
+fn backup_location_in(dir: &Path, file_location: &str) -> PathBuf {
+    let full_path = fs::canonicalize(file_location)
+        .or_else(|_| std::path::absolute(file_location))
+        .expect("Error getting the file's full path");
+    let path_hash = format!(
+        "{:x}",
+        Sha256::digest(full_path.to_string_lossy().as_bytes())
+    );
+
+    dir.join(path_hash).join(file_name(file_location))
+}
+
+fn file_name(file_location: &str) -> &str {
+    Path::new(file_location)
+        .file_name()
+        .and_then(|name| name.to_str())
+        .expect("Error getting file name")
+}
@@ -0,0 +1,88 @@
+use dirs::home_dir;
+use sha2::{Digest, Sha256};
+use std::{fs, path::PathBuf, process::Command};
+
+use crate::backends::{Cleaner, Word};
+
+// transcribes the file with the cleaner, reusing a cached transcript if one exists for the same audio, backend and options
+pub fn transcribe(cleaner: &mut dyn Cleaner, file_location: &str, use_cache: bool) -> Vec<Word> {
+    if !use_cache {
+        return cleaner.transcribe(file_location);
+    }
+
+    // without a hash of the audio there's nothing safe to key the cache on
+    let audio = match audio_hash(file_location) {
+        Some(a) => a,
+        None => return cleaner.transcribe(file_location),
+    };
+
+    let cache_file = cache_dir().join(cache_key(&audio, &cleaner.describe()) + ".json");
+
+    // if we've seen this audio before, we can skip transcribing it entirely
+    if let Ok(json_string) = fs::read_to_string(&cache_file) {
+        match serde_json::from_str(&json_string) {
+            Ok(words) => {
+                println!("Using cached transcript");
+                return words;
+            }
+            // a broken cache entry shouldn't stop the run - just transcribe it again
+            Err(_) => println!("Ignoring unreadable cached transcript"),
+        }
+    }
+
+    let words = cleaner.transcribe(file_location);
+
+    fs::create_dir_all(cache_dir()).expect("Error making cache directory");
+    fs::write(
+        cache_file,
+        serde_json::to_string(&words).expect("Error serializing transcript"),
+    )
+    .expect("Error writing transcript to cache");
+
+    words
+}
+
+// the directory the transcripts are kept in - ~/.project-soap/cache
+fn cache_dir() -> PathBuf {
+    home_dir()
+        .expect("Error getting user's home directory")
+        .join(".project-soap")
+        .join("cache")
+}
+
+// makes the cache key from the audio stream's hash and a description of the backend and its options
+fn cache_key(audio_hash: &str, backend: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(audio_hash);
+    hasher.update("\n");
+    hasher.update(backend);
+
+    format!("{:x}", hasher.finalize())
+}
+
+// hashes only the audio packets of the file, so changes to the container or other streams don't invalidate the cache
+fn audio_hash(file_location: &str) -> Option<String> {
+    let out = Command::new("ffmpeg")
+        .args(["-v", "error"])
+        .args(["-i", file_location])
+        // only the audio streams
+        .args(["-map", "0:a"])
+        // hashing the packets as-is is much faster than decoding them
+        .args(["-c", "copy"])
+        .args(["-f", "hash", "-hash", "sha256"])
+        .arg("-")
+        .output()
+        .expect("FFmpeg error");
+
+    // ffmpeg prints something like SHA256=...
+    let hash = String::from_utf8(out.stdout)
+        .expect("Error converting FFmpeg's hash to a string")
+        .trim()
+        .to_string();
+
+    if out.status.success() && hash.starts_with("SHA256=") {
+        Some(hash)
+    } else {
+        None
+    }
+}
@@ -2,46 +2,180 @@ use clap::Parser;
 use dirs::home_dir;
 use std::{fs, path::Path};
 
-use crate::backends::vosk_local;
+use crate::backends::{vosk_local, vosk_models};
 
-#[derive(Parser)]
+#[derive(Parser, Clone)]
 #[command(version, about, long_about = None)]
 pub struct Args {
     /// The backend that transcribes the audio
     #[command(subcommand)]
-    pub backend: Backend,
+    pub backend: Option<Backend>,
 
-    /// File to clean
+    /// File to clean - can also be a directory or a glob pattern like "season1/*.mkv" to clean a batch of files
     pub file_in: Option<String>,
 
-    /// Path to and name of cleaned file - default is overwriting the original file
+    /// Path to and name of cleaned file, or the directory to put cleaned files in for a batch - default is overwriting the original file
     #[arg(short, long, default_value_t = String::from(""))]
     pub out: String,
 
+    /// Also clean files in subdirectories when the input is a directory
+    #[arg(short, long, default_value_t = false)]
+    pub recursive: bool,
+
+    /// File extensions to clean when the input is a directory or a glob
+    #[arg(
+        long,
+        value_delimiter = ',',
+        default_value = "mp4,mkv,webm,mov,avi,m4a,mp3,wav,flac,ogg,opus,aac"
+    )]
+    pub extensions: Vec<String>,
+
+    /// Another backend to transcribe the file alongside the main one - can be given more than once, and their words are combined with --vote
+    #[arg(long, value_parser = backend_spec)]
+    pub ensemble: Vec<Backend>,
+
+    /// How the words from the --ensemble backends are combined
+    #[arg(long, value_enum, default_value_t = Vote::Union)]
+    pub vote: Vote,
+
+    /// How confident a backend has to be in a word for --vote confidence to keep it
+    #[arg(long, default_value_t = 0.9)]
+    pub vote_confidence: f32,
+
+    /// A more accurate backend to listen again to the parts around anything the main backend found or nearly found, like "whisper-x-local"
+    #[arg(long, value_parser = backend_spec)]
+    pub cascade: Option<Backend>,
+
+    /// How many seconds on either side of a suspicious word --cascade's backend listens to
+    #[arg(long, default_value_t = 1.)]
+    pub cascade_window: f32,
+
     /// Number of threads to run on - default is all system threads
     #[arg(value_parser = thread_number_in_range, short, long, default_value_t = std::thread::available_parallelism()
         .expect("Error getting system available parallelism")
         .into())]
     pub threads: usize,
 
+    /// Only censor words the backend is at least this confident in, from 0 to 1 - words without a confidence are always censored
+    #[arg(long, value_parser = confidence_in_range, default_value_t = 0.)]
+    pub censor_above: f32,
+
+    /// Flag words the backend is at least this confident in, but not enough to censor, for someone to review
+    #[arg(long, value_parser = confidence_in_range, default_value_t = 0.)]
+    pub review_above: f32,
+
+    /// Only censor words said by these speakers, like SPEAKER_00,SPEAKER_02 - words the backend couldn't put a speaker to are always censored
+    #[arg(long, value_delimiter = ',')]
+    pub censor_speakers: Vec<String>,
+
+    /// Never censor words said by these speakers
+    #[arg(long, value_delimiter = ',')]
+    pub keep_speakers: Vec<String>,
+
+    /// Check a speaker's words against their own list instead of the usual one, as SPEAKER=path - can be given more than once
+    #[arg(long, value_parser = speaker_list)]
+    pub speaker_list: Vec<(String, String)>,
+
+    /// Keep transcribing the cleaned file and removing what the last pass missed, until nothing new is found
     #[arg(long, default_value_t = false)]
     pub repeat: bool,
+
+    /// The most passes --repeat will make over a file
+    #[arg(long, value_parser = pass_number_at_least_one, default_value_t = 3)]
+    pub max_passes: usize,
+
+    /// Write a json report of what was removed to this path
+    #[arg(long)]
+    pub report: Option<String>,
+
+    /// Don't check the cleaned file against the original before replacing it
+    #[arg(long, default_value_t = false)]
+    pub no_verify: bool,
+
+    /// Also transcribe the censored parts of the cleaned file to make sure nothing on the list can still be heard
+    #[arg(long, default_value_t = false)]
+    pub verify_transcribe: bool,
+
+    /// Clean files even if they were already cleaned with the same list and settings
+    #[arg(long, default_value_t = false)]
+    pub force: bool,
+
+    /// Always transcribe the file, even if a cached transcript exists for it
+    #[arg(long, default_value_t = false)]
+    pub no_cache: bool,
+
+    /// Keep a backup of each file before it's overwritten, in ~/.project-soap/backups
+    #[arg(long, default_value_t = false)]
+    pub backup: bool,
+
+    /// Copy each file into this directory before it's overwritten
+    #[arg(long)]
+    pub keep_original: Option<String>,
+
+    /// Put the backup of the file back instead of cleaning it
+    #[arg(long, default_value_t = false)]
+    pub restore: bool,
+
+    /// Start a batch over instead of resuming it where an earlier run stopped
+    #[arg(long, default_value_t = false)]
+    pub no_resume: bool,
+
+    /// Watch this directory and clean files as they're added to it, putting them in --out - default output is a "cleaned" directory inside it
+    #[arg(long)]
+    pub watch: Option<String>,
+
+    /// How many seconds a watched file has to go without changing before it's considered done being written
+    #[arg(long, default_value_t = 10)]
+    pub settle: u64,
+
+    /// Report how much profanity is in the file instead of cleaning it
+    #[arg(long, default_value_t = false)]
+    pub rate: bool,
+
+    /// Rating bands for --rate, from mildest to harshest, as name=max expletives per minute
+    #[arg(long, value_parser = rating_bands, default_value = "clean=0,mild=0.5,moderate=2,strong=5")]
+    pub rating_bands: RatingBands,
+
+    /// Length in seconds of the windows --rate looks for the worst segments in
+    #[arg(long, default_value_t = 60.)]
+    pub rate_window: f32,
 }
 
-#[derive(clap::Subcommand, PartialEq)]
+#[derive(clap::Subcommand, PartialEq, Clone)]
 pub enum Backend {
     VoskLocal {
         /// Path to a Vosk model - default is the model included
-        #[arg(value_parser = model_location_exists, short, long, default_value_t = {
-
-        if cfg!(windows) {
-            String::from(home_dir().expect("Error getting user's home directory").to_str().expect("Error converting user's home directory to string")) + &String::from("\\.project-soap\\model\\vosk")
-        } else {
-            String::from(home_dir().expect("Error getting user's home directory").to_str().expect("Error converting user's home directory to string")) + &String::from("/.project-soap/model/vosk")
-        }
-    })]
+        #[arg(value_parser = model_location_exists, short, long, default_value_t = default_model_location())]
         model: String,
 
+        /// How loud audio has to be to count as speech, in dBFS - the audio is split into chunks at the silences, and long silences aren't transcribed at all
+        #[arg(long, default_value_t = -40., allow_hyphen_values = true)]
+        silence_threshold: f32,
+
+        /// How many seconds chunks of audio split in the middle of long speech overlap each other, so words at the edges aren't cut in half
+        #[arg(long, default_value_t = 1.)]
+        overlap: f32,
+
+        /// How many of the model's best guesses to look at for each utterance - a listed word in any guess likely enough is censored
+        #[arg(long, default_value_t = 0)]
+        alternatives: u16,
+
+        /// How likely a guess other than the best one has to be, from 0 to 1, for its listed words to be censored
+        #[arg(long, value_parser = confidence_in_range, default_value_t = 0.2)]
+        alternative_threshold: f32,
+
+        /// Listen again to the audio around words the model wasn't sure of, only letting it hear words on the list - needs a model that supports grammars, like the small or medium ones
+        #[arg(long, default_value_t = false)]
+        grammar_pass: bool,
+
+        /// How unsure of a word the model has to be, from 0 to 1, for --grammar-pass to listen to it again
+        #[arg(long, value_parser = confidence_in_range, default_value_t = 0.9)]
+        grammar_below: f32,
+
+        /// Path to a Vosk speaker model, to tell who said each word - the voices are labeled SPEAKER_00, SPEAKER_01 and so on, in the order they're first heard
+        #[arg(long)]
+        speaker_model: Option<String>,
+
         /// Call a subcommand
         #[command(subcommand)]
         command: Option<vosk_local::VoskLocalCommands>,
@@ -60,12 +194,107 @@ pub enum Backend {
     ParakeetLocal {},
 }
 
-// Input validator - checks if the model path exists
+#[derive(clap::ValueEnum, PartialEq, Clone, Copy, Debug)]
+pub enum Vote {
+    /// Keep every word any backend heard
+    Union,
+    /// Keep words more than half of the backends heard
+    Majority,
+    /// Keep words any backend is at least --vote-confidence sure of
+    Confidence,
+}
+
+// just a backend and its options, for backends given as options to others
+#[derive(Parser)]
+struct BackendOnly {
+    #[command(subcommand)]
+    backend: Backend,
+}
+
+// Input validator - parses a backend and its options, like "vosk-local --model ~/models/small"
+fn backend_spec(b: &str) -> Result<Backend, String> {
+    // it's all in quotes, so the shell never got to turn ~ into the home directory
+    let home = home_dir()
+        .and_then(|h| h.to_str().map(|h| h.to_string()))
+        .unwrap_or_default();
+    let options = b.split_whitespace().map(|o| match o.strip_prefix("~/") {
+        Some(rest) if !home.is_empty() => format!("{}/{}", home, rest),
+        _ => o.to_string(),
+    });
+
+    BackendOnly::try_parse_from(std::iter::once(String::from("project-soap")).chain(options))
+        .map(|b| b.backend)
+        .map_err(|e| e.to_string())
+}
+
+#[derive(Clone)]
+pub struct RatingBands(pub Vec<(String, f32)>);
+
+// Input validator - parses a list of rating bands like "clean=0,mild=0.5" and checks they go from mildest to harshest
+fn rating_bands(b: &str) -> Result<RatingBands, String> {
+    let mut bands: Vec<(String, f32)> = Vec::new();
+
+    for band in b.split(',') {
+        let (name, max) = band
+            .split_once('=')
+            .ok_or(format!("'{band}' should look like name=max"))?;
+        let max: f32 = max
+            .trim()
+            .parse()
+            .map_err(|_| format!("'{max}' isn't a number of expletives per minute"))?;
+
+        if bands.last().is_some_and(|(_, last)| *last > max) {
+            return Err(String::from(
+                "Rating bands must go from mildest to harshest",
+            ));
+        }
+
+        bands.push((name.trim().to_string(), max));
+    }
+
+    Ok(RatingBands(bands))
+}
+
+// Input validator - parses a speaker and the list for them, like "SPEAKER_01=~/lists/strict.txt", and checks the list exists
+fn speaker_list(s: &str) -> Result<(String, String), String> {
+    let (speaker, location) = s
+        .split_once('=')
+        .ok_or(format!("'{s}' should look like SPEAKER=path"))?;
+
+    if !Path::new(location).is_file() {
+        return Err(format!("List '{location}' does not exist"));
+    }
+
+    Ok((speaker.trim().to_string(), location.to_string()))
+}
+
+// where get-model puts the model if it isn't told otherwise, and where it's looked for
+pub fn default_model_location() -> String {
+    if cfg!(windows) {
+        String::from(
+            home_dir()
+                .expect("Error getting user's home directory")
+                .to_str()
+                .expect("Error converting user's home directory to string"),
+        ) + &String::from("\\.project-soap\\model\\vosk")
+    } else {
+        String::from(
+            home_dir()
+                .expect("Error getting user's home directory")
+                .to_str()
+                .expect("Error converting user's home directory to string"),
+        ) + &String::from("/.project-soap/model/vosk")
+    }
+}
+
+// Input validator - checks if the model path exists - it can also be the name of a model installed with "models install"
 fn model_location_exists(m: &str) -> Result<String, String> {
     let model_path = Path::new(m);
 
     if model_path.exists() {
         Ok(m.to_string())
+    } else if let Some(installed) = vosk_models::installed_location(m) {
+        Ok(installed)
     } else {
         let r = fs::DirBuilder::new().recursive(true).create(model_path);
 
@@ -95,3 +324,29 @@ fn thread_number_in_range(t: &str) -> Result<usize, String> {
         Err(format!("Thread number not in range {}-{}", 1, max_threads))
     }
 }
+
+// Input validator - makes sure the confidence threshold is between 0 and 1
+fn confidence_in_range(c: &str) -> Result<f32, String> {
+    let confidence: f32 = c
+        .parse()
+        .map_err(|_| format!("'{c}' isn't a correct confidence"))?;
+
+    if (0.0..=1.0).contains(&confidence) {
+        Ok(confidence)
+    } else {
+        Err(String::from("Confidence has to be between 0 and 1"))
+    }
+}
+
+// Input validator - makes sure there's at least one pass
+fn pass_number_at_least_one(p: &str) -> Result<usize, String> {
+    let pass_number: usize = p
+        .parse()
+        .map_err(|_| format!("'{p}' isn't a correct number of passes"))?;
+
+    if pass_number >= 1 {
+        Ok(pass_number)
+    } else {
+        Err(String::from("There has to be at least one pass"))
+    }
+}
@@ -0,0 +1,115 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+// whether the input can match more than one file - a directory or a glob pattern
+pub fn is_batch(file_in: &str) -> bool {
+    let path = Path::new(file_in);
+
+    // a file that's really there is just a file, even with brackets in its name like yt-dlp's "Title [videoid].mp4"
+    if path.is_file() {
+        return false;
+    }
+
+    path.is_dir() || file_in.contains(['*', '?', '['])
+}
+
+// turns the input into a list of files to clean, each paired with where it should go relative to the output directory
+pub fn expand(file_in: &str, recursive: bool, extensions: &[String]) -> Vec<(String, PathBuf)> {
+    let path = Path::new(file_in);
+
+    let mut files: Vec<(PathBuf, PathBuf)> = if path.is_dir() {
+        let mut found = Vec::new();
+        walk_dir(path, recursive, &mut found);
+
+        // keeps the directory structure under the output directory
+        found
+            .into_iter()
+            .map(|f| {
+                let relative = f
+                    .strip_prefix(path)
+                    .expect("Error getting path relative to input directory")
+                    .to_path_buf();
+                (f, relative)
+            })
+            .collect()
+    } else if is_batch(file_in) {
+        let base = glob_base(file_in);
+
+        // keeps the directory structure under the part of the pattern that isn't a wildcard, so "**/*.mkv" can't put s1/e01.mkv and s2/e01.mkv in the same place
+        glob::glob(file_in)
+            .expect("Invalid glob pattern")
+            .flatten()
+            .filter(|f| f.is_file())
+            .map(|f| {
+                let relative = f
+                    .strip_prefix(&base)
+                    .ok()
+                    .filter(|r| !r.as_os_str().is_empty())
+                    .map(|r| r.to_path_buf())
+                    .unwrap_or_else(|| {
+                        PathBuf::from(f.file_name().expect("Error getting file name"))
+                    });
+                (f, relative)
+            })
+            .collect()
+    } else {
+        // a single file is cleaned no matter what its extension is
+        return vec![(
+            file_in.to_string(),
+            PathBuf::from(path.file_name().unwrap_or_default()),
+        )];
+    };
+
+    files.retain(|(f, _)| has_extension(f, extensions) && !is_hidden(f));
+    files.sort();
+
+    files
+        .into_iter()
+        .map(|(f, relative)| {
+            (
+                f.to_str()
+                    .expect("Error converting file path to string")
+                    .to_string(),
+                relative,
+            )
+        })
+        .collect()
+}
+
+// the directories at the start of a glob pattern before the first wildcard - "season1/*.mkv" gives "season1", and "**/*.mkv" gives nothing
+fn glob_base(pattern: &str) -> PathBuf {
+    Path::new(pattern)
+        .components()
+        .take_while(|c| !c.as_os_str().to_string_lossy().contains(['*', '?', '[']))
+        .collect()
+}
+
+// adds every file in the directory to found, going into subdirectories if recursive is set
+fn walk_dir(dir: &Path, recursive: bool, found: &mut Vec<PathBuf>) {
+    for entry in fs::read_dir(dir).expect("Error reading input directory") {
+        let entry_path = entry.expect("Error reading input directory").path();
+
+        if entry_path.is_dir() {
+            if recursive {
+                walk_dir(&entry_path, recursive, found);
+            }
+        } else {
+            found.push(entry_path);
+        }
+    }
+}
+
+fn has_extension(file: &Path, extensions: &[String]) -> bool {
+    file.extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| extensions.iter().any(|x| x.eq_ignore_ascii_case(e)))
+}
+
+// hidden files include the temporary files cleaned files are written to before they replace the original
+fn is_hidden(file: &Path) -> bool {
+    file.file_name()
+        .and_then(|name| name.to_str())
+        .is_some_and(|name| name.starts_with('.'))
+}
@@ -1,141 +1,539 @@
 // For the CLI
 mod cli;
-use clap::Parser;
+use clap::{CommandFactory, Parser};
 use dirs::home_dir;
+use sha2::{Digest, Sha256};
 use std::fs;
 use std::process::Command;
 
+mod audio;
 mod backends;
+mod backup;
+mod cache;
+mod inputs;
+mod queue;
+mod rate;
+mod report;
+mod speakers;
+mod tags;
+mod verify;
+mod watch;
 
 use std::{
-    collections::HashSet,
+    any::Any,
+    collections::HashMap,
     fs::File,
     io::{self, BufRead},
+    panic::{self, AssertUnwindSafe},
     path::Path,
     time::Instant,
 };
 
+// maps each word on the list to the category it was listed under
+pub type Expletives = HashMap<String, String>;
+
+// the category for words listed before any [category] line
+pub const UNCATEGORIZED: &str = "uncategorized";
+
 fn main() {
     // Parses the CLI arguments
     let args = cli::Args::parse();
 
-    let file_location = args.file_in.clone();
-    let mut out_location = args.out.clone();
-    let repeat = args.repeat.clone();
+    // the review band is the words between the two, so it would always be empty the other way around
+    if args.review_above > args.censor_above {
+        cli::Args::command()
+            .error(
+                clap::error::ErrorKind::ArgumentConflict,
+                "--review-above can't be more than --censor-above",
+            )
+            .exit();
+    }
 
     let start = Instant::now();
 
-    let cleaner = match args.backend {
-        cli::Backend::VoskLocal { .. } => backends::vosk_local::VoskLocal::from_args(args),
-        cli::Backend::WhisperXLocal { .. } => {
-            backends::whisperx_local::WhisperXLocal::from_args(args)
-        }
-        cli::Backend::ParakeetLocal { .. } => {
-            backends::parakeet_local::ParakeetLocal::from_args(args)
+    // restoring doesn't need a backend, so it happens before one is loaded
+    if args.restore {
+        let file_in = args
+            .file_in
+            .clone()
+            .expect("Please input a file to restore");
+
+        for (file_location, _) in inputs::expand(&file_in, args.recursive, &args.extensions) {
+            if backup::restore(&file_location) {
+                println!("Restored {}", file_location);
+            } else {
+                println!("No backup of {} to restore", file_location);
+            }
         }
-    };
+
+        return;
+    }
+
+    let cleaner = backends::from_args(args.clone());
 
     let mut cleaner = match cleaner {
         Some(c) => c,
         None => return,
     };
 
-    let file_location = file_location.expect("Please input a file to clean");
+    // watching keeps the same backend loaded for every file that shows up
+    if let Some(watch_dir) = &args.watch {
+        watch::watch(cleaner.as_mut(), watch_dir, &args);
+        return;
+    }
 
-    let overwrite;
+    let file_in = args.file_in.clone().expect("Please input a file to clean");
 
-    let binding = file_location.clone();
-    let out_file_name = Path::new(&binding)
-        .file_name()
-        .expect("error getting in file name to find output json")
-        .to_str()
-        .expect("Error converting file name to string");
+    // a directory or a glob can match any number of files, so they're always treated as a batch
+    let batch = inputs::is_batch(&file_in);
+    let files = inputs::expand(&file_in, args.recursive, &args.extensions);
 
-    if out_location == "" {
-        overwrite = true;
+    if files.is_empty() {
+        println!("No files to clean");
+        return;
+    }
 
-        if cfg!(windows) {
-            out_location = String::from(
-                home_dir()
-                    .expect("Error getting user's home directory")
-                    .to_str()
-                    .expect("Error converting user's home directory to string"),
-            ) + &String::from("\\.project-soap\\temp\\")
-                + out_file_name
-        } else {
-            out_location = String::from(
-                home_dir()
-                    .expect("Error getting user's home directory")
-                    .to_str()
-                    .expect("Error converting user's home directory to string"),
-            ) + &String::from("/.project-soap/temp/")
-                + out_file_name
+    // rating only looks at the files, so it stops before anything gets cleaned
+    if args.rate {
+        let expletives = load_expletives();
+        let policy = speakers::Policy::from_args(&args);
+        let mut ratings = Vec::new();
+
+        for (file_location, _) in &files {
+            let words = cache::transcribe(cleaner.as_mut(), file_location, !args.no_cache);
+            policy.warn_if_unlabeled(&words, file_location);
+
+            let (hits, _) = split_by_confidence(policy.find_expletives(&expletives, words), &args);
+
+            let rating = rate::Rating::new(
+                file_location,
+                &hits,
+                &policy.all_expletives(&expletives),
+                &args.rating_bands.0,
+                args.rate_window,
+            );
+
+            // one file that can't be rated shouldn't stop the rest of the batch
+            match rating {
+                Some(rating) => {
+                    rating.print();
+                    ratings.push(rating);
+                }
+                None => println!(
+                    "Couldn't get the length of {}, so it can't be rated",
+                    file_location
+                ),
+            }
         }
-    } else {
-        overwrite = false;
+
+        if let Some(report_location) = args.report.as_ref().filter(|_| !ratings.is_empty()) {
+            report::write(&ratings, report_location, batch);
+        }
+
+        return;
     }
 
-    let mut count = remove_expletives(
-        load_expletives(),
-        cleaner.transcribe(),
-        file_location.clone(),
-        out_location.clone(),
-    );
-
-    let mut temp_count = 1;
-    let mut iterations = 0;
-
-    if repeat && count > 0 {
-        while temp_count != 0 {
-            clean_up(overwrite, file_location.clone(), out_location.clone());
-            temp_count = remove_expletives(
-                load_expletives(),
-                cleaner.transcribe(),
-                file_location.clone(),
+    // batches keep track of each file on disk, so they can be resumed if they're interrupted
+    let mut job_queue = if batch {
+        let file_list: Vec<String> = files.iter().map(|(f, _)| f.clone()).collect();
+        Some(queue::Queue::load(
+            &file_in,
+            &args.out,
+            &file_list,
+            !args.no_resume,
+        ))
+    } else {
+        None
+    };
+
+    let mut reports = Vec::new();
+    let mut total_count = 0;
+    let mut total_passes = 0;
+    let mut already_done = 0;
+    let mut failed = Vec::new();
+
+    for (file_location, relative_location) in &files {
+        if job_queue.as_ref().is_some_and(|q| q.is_done(file_location)) {
+            already_done += 1;
+            continue;
+        }
+
+        // in a batch, --out is the directory the cleaned files go in
+        let out_location = if batch && !args.out.is_empty() {
+            let out_location = Path::new(&args.out).join(relative_location);
+
+            if let Some(parent) = out_location.parent() {
+                fs::create_dir_all(parent).expect("Error making output directory");
+            }
+
+            out_location
+                .to_str()
+                .expect("Error converting output path to string")
+                .to_string()
+        } else {
+            args.out.clone()
+        };
+
+        let file_start = Instant::now();
+
+        let (outcome, report) = match job_queue.as_mut() {
+            Some(job_queue) => {
+                // one bad file shouldn't lose the rest of the batch
+                let result = panic::catch_unwind(AssertUnwindSafe(|| {
+                    clean_file(
+                        cleaner.as_mut(),
+                        file_location,
+                        out_location.clone(),
+                        &args,
+                        &mut |state| job_queue.set_state(file_location, state, None),
+                    )
+                }));
+
+                match result {
+                    Ok(r) => {
+                        job_queue.set_state(file_location, queue::JobState::Verified, None);
+                        r
+                    }
+                    Err(e) => {
+                        job_queue.set_state(
+                            file_location,
+                            queue::JobState::Failed,
+                            Some(panic_message(e)),
+                        );
+
+                        // don't leave a half written file behind
+                        if out_location.is_empty() {
+                            let _ = fs::remove_file(temp_out_location(file_location));
+                        }
+
+                        failed.push(file_location.clone());
+                        continue;
+                    }
+                }
+            }
+            None => clean_file(
+                cleaner.as_mut(),
+                file_location,
                 out_location.clone(),
-            );
-            count += temp_count;
-            iterations += 1;
+                &args,
+                &mut |_| {},
+            ),
+        };
+
+        let (count, passes) = match outcome {
+            Outcome::Cleaned(count, passes) => (count, passes),
+            Outcome::Skipped => (0, 0),
+        };
+
+        // files with nothing to remove aren't written out by ffmpeg, so they're copied over as they are - otherwise the cleaned batch would be missing them
+        if batch && !args.out.is_empty() && outcome == Outcome::Cleaned(0, passes) {
+            fs::copy(file_location, &out_location).expect("Error copying file to output directory");
         }
-    } else {
-        if count != 0 {
-            clean_up(overwrite, file_location, out_location);
+
+        if batch && outcome != Outcome::Skipped {
+            if args.repeat {
+                println!(
+                    "{}: removed {} expletives in {} passes",
+                    file_location, count, passes
+                );
+            } else {
+                println!("{}: removed {} expletives", file_location, count);
+            }
         }
+
+        reports.push(report.finish(file_start.elapsed()));
+        total_count += count;
+        total_passes += passes;
     }
 
     let end = Instant::now();
-    println!("Removed {} expletives.", count);
-    if repeat {
+
+    if let Some(report_location) = &args.report {
+        report::write(&reports, report_location, batch);
+    }
+
+    if batch {
+        println!(
+            "Removed {} expletives from {} files.",
+            total_count,
+            reports.len()
+        );
+
+        if already_done > 0 {
+            println!("Skipped {} files finished by an earlier run", already_done);
+        }
+
+        if !failed.is_empty() {
+            println!("Failed to clean {} files:", failed.len());
+            for file in &failed {
+                println!("  {}", file);
+            }
+        }
+    } else {
+        println!("Removed {} expletives.", total_count);
+    }
+
+    if args.repeat {
         println!(
-            "Filtering took {:#?} and {} iterations",
+            "Filtering took {:#?} and {} passes",
             end.duration_since(start),
-            iterations
+            total_passes
         );
     } else {
         println!("Filtering took {:#?}", end.duration_since(start));
     }
 }
 
-// checks each word against the HashSet, makes a filter string to remove it if it is on the list, and then calls ffmpeg to remove it
+// where the cleaned file is written before it's renamed over the original - right next to it, so the rename never has to cross drives
+fn temp_out_location(file_location: &str) -> String {
+    let path = Path::new(file_location);
+    let out_file_name = path
+        .file_name()
+        .expect("error getting in file name to find output json")
+        .to_str()
+        .expect("Error converting file name to string");
+
+    // keeps the extension on the end so ffmpeg still knows what to write
+    path.with_file_name(format!(".soap-tmp.{}", out_file_name))
+        .to_str()
+        .expect("Error converting temporary file path to string")
+        .to_string()
+}
+
+// what clean_file did with a file
+#[derive(PartialEq, Clone, Copy)]
+pub enum Outcome {
+    // how many expletives were removed, and how many passes it took
+    Cleaned(usize, usize),
+    // the output was already cleaned with this list and these settings, so nothing was done - not even writing the output
+    Skipped,
+}
+
+// cleans a single file, returning what happened to it and the report for it - progress is told each time the file reaches a new stage
+fn clean_file(
+    cleaner: &mut dyn backends::Cleaner,
+    file_location: &str,
+    mut out_location: String,
+    args: &cli::Args,
+    progress: &mut dyn FnMut(queue::JobState),
+) -> (Outcome, report::Report) {
+    let repeat = args.repeat;
+    let file_location = file_location.to_string();
+
+    let overwrite;
+
+    if out_location == "" {
+        overwrite = true;
+        out_location = temp_out_location(&file_location);
+    } else {
+        overwrite = false;
+    }
+
+    // when overwriting, the cleaned file ends up where the original was
+    let final_location = if overwrite {
+        file_location.clone()
+    } else {
+        out_location.clone()
+    };
+
+    let mut report = report::Report::new(&file_location, &final_location, cleaner.describe());
+
+    // re-cleaning a file that's already clean would only lose quality re-encoding it again
+    if !args.force && tags::is_cleaned(&final_location, &cleaner.describe(), &tags::settings(args))
+    {
+        println!(
+            "{} was already cleaned with this list and these settings, skipping",
+            final_location
+        );
+        return (Outcome::Skipped, report);
+    }
+
+    // every word censored so far - each pass re-censors all of them from the original, so the audio is only ever encoded once
+    let mut censored: Vec<backends::Word> = Vec::new();
+    let mut passes = 0;
+    let max_passes = if repeat { args.max_passes } else { 1 };
+
+    // the first pass listens to the original, and every pass after that listens to the last pass's output
+    let mut transcribe_location = file_location.clone();
+
+    // speakers can have their own lists, so hits are put in categories from all of them
+    let all_expletives = speakers::Policy::from_args(args).all_expletives(&load_expletives());
+
+    while passes < max_passes {
+        passes += 1;
+
+        let (found, review) = run_pass(
+            cleaner,
+            &transcribe_location,
+            &censored,
+            args,
+            &mut report,
+            progress,
+        );
+        report.add_review(&review, &all_expletives, passes);
+
+        if repeat {
+            println!("Pass {} found {} expletives", passes, found.len());
+            for word in &found {
+                match &word.speaker {
+                    Some(speaker) => {
+                        println!("  {} at {:.2}s ({})", word.word, word.start, speaker)
+                    }
+                    None => println!("  {} at {:.2}s", word.word, word.start),
+                }
+            }
+        }
+
+        if found.is_empty() {
+            if passes == 1 {
+                println!("Nothing to remove");
+            }
+            break;
+        }
+
+        report.add_hits(&found, &all_expletives, passes);
+        censored.extend(found);
+
+        let censor_start = Instant::now();
+        remove_expletives(
+            &censored,
+            file_location.clone(),
+            out_location.clone(),
+            &cleaner.describe(),
+            &tags::settings(args),
+        );
+        report.add_stage_time("censor", censor_start.elapsed());
+        progress(queue::JobState::Censored);
+
+        transcribe_location = out_location.clone();
+    }
+
+    report.print_review();
+
+    let count = censored.len();
+
+    if count != 0 {
+        // the original only needs backing up once, before it's overwritten
+        if overwrite && (args.backup || args.keep_original.is_some()) {
+            backup::back_up(&file_location, args.keep_original.as_deref());
+        }
+
+        verify_pass(
+            cleaner,
+            &file_location,
+            &out_location,
+            overwrite,
+            &censored,
+            args,
+            &mut report,
+        );
+
+        let clean_up_start = Instant::now();
+        clean_up(overwrite, file_location, out_location);
+        report.add_stage_time("clean_up", clean_up_start.elapsed());
+    }
+
+    (Outcome::Cleaned(count, passes), report)
+}
+
+// checks the cleaned file before it's allowed anywhere near the original - a failed check removes it and stops there
+fn verify_pass(
+    cleaner: &mut dyn backends::Cleaner,
+    file_location: &str,
+    out_location: &str,
+    overwrite: bool,
+    hits: &[backends::Word],
+    args: &cli::Args,
+    report: &mut report::Report,
+) {
+    if args.no_verify {
+        return;
+    }
+
+    let verify_start = Instant::now();
+
+    let mut result = verify::check_output(file_location, out_location);
+    if result.is_ok() && args.verify_transcribe {
+        result = verify::check_censored(cleaner, out_location, hits, &load_expletives());
+    }
+
+    report.add_stage_time("verify", verify_start.elapsed());
+
+    if let Err(message) = result {
+        // when overwriting, the cleaned file is only a temporary file, so there's nothing worth keeping
+        if overwrite {
+            let _ = fs::remove_file(out_location);
+        }
+
+        panic!("Cleaned file failed verification: {}", message);
+    }
+}
+
+// transcribes the file and finds the words on the list that haven't already been censored, timing it for the report
+fn run_pass(
+    cleaner: &mut dyn backends::Cleaner,
+    transcribe_location: &str,
+    censored: &[backends::Word],
+    args: &cli::Args,
+    report: &mut report::Report,
+    progress: &mut dyn FnMut(queue::JobState),
+) -> (Vec<backends::Word>, Vec<backends::Word>) {
+    let expletives = load_expletives();
+    let policy = speakers::Policy::from_args(args);
+
+    let transcribe_start = Instant::now();
+    let words = cache::transcribe(cleaner, transcribe_location, !args.no_cache);
+    report.add_stage_time("transcribe", transcribe_start.elapsed());
+    progress(queue::JobState::Transcribed);
+
+    policy.warn_if_unlabeled(&words, transcribe_location);
+
+    // anything inside audio that's already been censored is silence now, so it doesn't need another look
+    let found = policy
+        .find_expletives(&expletives, words)
+        .into_iter()
+        .filter(|w| !censored.iter().any(|c| w.start < c.end && c.start < w.end))
+        .collect();
+
+    split_by_confidence(found, args)
+}
+
+// sorts found words into the ones to censor and the ones to flag for review, dropping any the backend wasn't sure enough of for either
+fn split_by_confidence(
+    found: Vec<backends::Word>,
+    args: &cli::Args,
+) -> (Vec<backends::Word>, Vec<backends::Word>) {
+    let mut censor = Vec::new();
+    let mut review = Vec::new();
+
+    for word in found {
+        // backends that don't give a confidence are taken at their word
+        match word.confidence {
+            None => censor.push(word),
+            Some(c) if c >= args.censor_above => censor.push(word),
+            Some(c) if c >= args.review_above => review.push(word),
+            Some(_) => {}
+        }
+    }
+
+    (censor, review)
+}
+
+// makes a filter string to silence each of the words, and then calls ffmpeg to remove them
 fn remove_expletives(
-    expletives: HashSet<String>,
-    times_in: Vec<backends::Word>,
+    to_censor: &[backends::Word],
     file_location: String,
     out_location: String,
-) -> u16 {
-    let mut count: u16 = 0;
-
-    let to_remove = times_in
-        .iter()
-        .filter(|w| expletives.contains(&w.word))
-        .map(|w| {
-            count += 1;
-            format!(
-                // I really need to read ffmpeg's docs or something, because this is almost greek to me
-                "volume=enable='between(t,{},{})':volume=0, ",
-                w.start, w.end
-            )
-        });
+    backend: &str,
+    settings: &str,
+) {
+    let to_remove = to_censor.iter().map(|w| {
+        format!(
+            // I really need to read ffmpeg's docs or something, because this is almost greek to me
+            "volume=enable='between(t,{},{})':volume=0, ",
+            w.start, w.end
+        )
+    });
 
     // Stores the list of filters that determine which audio segments will be cut out
     let mut filter_string: String = to_remove.collect();
@@ -147,60 +545,99 @@ fn remove_expletives(
     #[cfg(debug_assertions)]
     println!("{}", filter_string);
 
-    if filter_string.len() != 0 {
-        // This builds the command.
-        let out = Command::new("ffmpeg")
-            .arg("-y")
-            .arg("-i")
-            .arg(file_location)
-            .arg("-af")
-            .arg(filter_string)
-            .args(["-c:v", "copy"])
-            .arg(&format!("{}", out_location))
-            .output()
-            .expect("failed to execute process");
-
-        #[cfg(debug_assertions)]
-        println!("{:?}", out);
-    } else {
-        println!("Nothing to remove");
+    // This builds the command.
+    let out = Command::new("ffmpeg")
+        .arg("-y")
+        .arg("-i")
+        .arg(&file_location)
+        .arg("-af")
+        .arg(filter_string)
+        // keeps every stream, not just ffmpeg's pick of one video and one audio - everything but the audio is copied as is
+        .args(["-map", "0"])
+        .args([
+            "-c:v", "copy", "-c:s", "copy", "-c:d", "copy", "-c:t", "copy",
+        ])
+        // marks the file as cleaned, so later runs can skip it
+        .args(tags::ffmpeg_args(&out_location, backend, settings))
+        .arg(&format!("{}", out_location))
+        .output()
+        .expect("failed to execute process");
+
+    #[cfg(debug_assertions)]
+    println!("{:?}", out);
+
+    // a failed or cut short output must never make it over the original, even with --no-verify
+    if !out.status.success() {
+        let _ = fs::remove_file(&out_location);
+        panic!(
+            "FFmpeg couldn't censor {}: {}",
+            file_location,
+            String::from_utf8_lossy(&out.stderr).trim()
+        );
     }
+}
 
-    return count;
+// keeps only the words that are on the list
+fn find_expletives(expletives: &Expletives, words: Vec<backends::Word>) -> Vec<backends::Word> {
+    words
+        .into_iter()
+        .filter(|w| expletives.contains_key(&w.word))
+        .collect()
+}
+
+// where the list of expletives lives - ~/.project-soap/list.txt
+pub fn list_location() -> String {
+    #[cfg(unix)]
+    return home_dir()
+        .expect("Error getting user's home directory")
+        .to_str()
+        .expect("Error converting user's home directory to string")
+        .to_string()
+        + "/.project-soap/list.txt";
+
+    #[cfg(windows)]
+    return home_dir()
+        .expect("Error getting user's home directory")
+        .to_str()
+        .expect("Error converting user's home directory to string")
+        .to_string()
+        + "\\.project-soap\\list.txt";
+}
+
+// hashes the list file, so it's possible to tell exactly which list a file was cleaned with
+pub fn list_hash() -> String {
+    list_hash_of(&list_location())
+}
+
+// hashes any list file, like the ones given to --speaker-list
+pub fn list_hash_of(location: &str) -> String {
+    let list = fs::read(location).expect("Error reading list of expletives");
+
+    format!("{:x}", Sha256::digest(list))
+}
+
+// loads the expletives from the list
+fn load_expletives() -> Expletives {
+    load_expletives_from(&list_location())
 }
 
 // loads the expletives from a text file
-fn load_expletives() -> HashSet<String> {
-    // initializes a HashSet to put them into
-    let mut list = HashSet::<String>::new();
+pub fn load_expletives_from(location: &str) -> Expletives {
+    // initializes a HashMap to put them into
+    let mut list = Expletives::new();
 
     // reads the lines of the file
-    #[cfg(unix)]
-    let lines = read_lines(
-        home_dir()
-            .expect("Error getting user's home directory")
-            .to_str()
-            .expect("Error converting user's home directory to string")
-            .to_string()
-            + "/.project-soap/list.txt",
-    )
-    .expect("Unix: Error getting list of expletives");
+    let lines = read_lines(location).expect("Error getting list of expletives");
 
-    #[cfg(windows)]
-    let lines = read_lines(
-        home_dir()
-            .expect("Error getting user's home directory")
-            .to_str()
-            .expect("Error converting user's home directory to string")
-            .to_string()
-            + "\\.project-soap\\list.txt",
-    )
-    .expect("Windows: Error getting list of expletives");
+    // words go under the last [category] line above them
+    let mut category = String::from(UNCATEGORIZED);
 
     // Consumes the iterator, returns an (Optional) String
     for line in lines.flatten() {
-        if !line.starts_with("/") && line != "" {
-            list.insert(line);
+        if line.starts_with("[") && line.ends_with("]") {
+            category = line[1..line.len() - 1].to_string();
+        } else if !line.starts_with("/") && line != "" {
+            list.insert(line, category.clone());
         }
     }
 
@@ -218,15 +655,9 @@ fn load_expletives() -> HashSet<String> {
 fn clean_up(overwrite: bool, file_location: String, out_location: String) {
     // if we are overwriting the original file
     if overwrite {
-        // read in the clean file
-        let clean_file =
-            fs::read(out_location.clone()).expect("Error reading clean file for clean up");
-
-        println!("{}", file_location);
-        // then write it to the original
-        fs::write(file_location.clone(), clean_file).expect("Error copying clean file to original");
-
-        fs::remove_file(out_location).expect("Error removing temporary files");
+        // renaming is atomic, so the original is either left alone or completely replaced - never half written
+        fs::rename(out_location, file_location.clone())
+            .expect("Error replacing original with clean file");
     }
 
     let temp_dir;
@@ -254,5 +685,20 @@ fn clean_up(overwrite: bool, file_location: String, out_location: String) {
         .to_str()
         .expect("Error converting file name to string");
 
-    fs::remove_file(temp_dir + out_file_name + ".json").expect("Error removing transcribed file");
+    // only some backends leave their transcript here
+    let transcript = temp_dir + out_file_name + ".json";
+    if Path::new(&transcript).exists() {
+        fs::remove_file(transcript).expect("Error removing transcribed file");
+    }
+}
+
+// gets the message out of a caught panic
+fn panic_message(payload: Box<dyn Any + Send>) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        String::from("unknown error")
+    }
 }
@@ -0,0 +1,133 @@
+use dirs::home_dir;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{fs, path::PathBuf};
+
+// how far a file in a batch has gotten
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum JobState {
+    Pending,
+    Transcribed,
+    Censored,
+    Verified,
+    Failed,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Job {
+    file: String,
+    state: JobState,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+// the files in a batch and how far each one has gotten, saved to ~/.project-soap/queues after every change so a batch can pick up where it left off
+#[derive(Serialize, Deserialize)]
+pub struct Queue {
+    // the input the batch was started with - a queue is only resumed for the same input and output
+    input: String,
+    out: String,
+    jobs: Vec<Job>,
+}
+
+impl Queue {
+    // resumes the saved queue if it's for the same batch and isn't finished, otherwise starts a new one
+    pub fn load(input: &str, out: &str, files: &[String], resume: bool) -> Queue {
+        if resume {
+            let saved: Option<Queue> = fs::read_to_string(queue_location(input, out))
+                .ok()
+                .and_then(|json| serde_json::from_str(&json).ok());
+
+            if let Some(mut queue) = saved {
+                let unfinished = queue.jobs.iter().any(|j| j.state != JobState::Verified);
+
+                if queue.input == input && queue.out == out && unfinished {
+                    // files added since the batch was started still need doing
+                    for file in files {
+                        if !queue.jobs.iter().any(|j| &j.file == file) {
+                            queue.jobs.push(Job::new(file));
+                        }
+                    }
+
+                    println!(
+                        "Resuming batch - {} of {} files already done",
+                        queue.done(),
+                        queue.jobs.len()
+                    );
+
+                    queue.save();
+                    return queue;
+                }
+            }
+        }
+
+        let queue = Queue {
+            input: input.to_string(),
+            out: out.to_string(),
+            jobs: files.iter().map(|f| Job::new(f)).collect(),
+        };
+        queue.save();
+
+        queue
+    }
+
+    // whether the file was already finished by an earlier run of the batch
+    pub fn is_done(&self, file: &str) -> bool {
+        self.jobs
+            .iter()
+            .any(|j| j.file == file && j.state == JobState::Verified)
+    }
+
+    pub fn set_state(&mut self, file: &str, state: JobState, error: Option<String>) {
+        if let Some(job) = self.jobs.iter_mut().find(|j| j.file == file) {
+            job.state = state;
+            job.error = error;
+        }
+
+        self.save();
+    }
+
+    fn done(&self) -> usize {
+        self.jobs
+            .iter()
+            .filter(|j| j.state == JobState::Verified)
+            .count()
+    }
+
+    // writes to a temporary file first so a crash while saving can't leave a half written queue behind
+    fn save(&self) {
+        let location = queue_location(&self.input, &self.out);
+        let temp_location = location.with_extension("json.tmp");
+
+        fs::create_dir_all(location.parent().expect("Queue location has no parent"))
+            .expect("Error making queue directory");
+        fs::write(
+            &temp_location,
+            serde_json::to_string_pretty(self).expect("Error serializing job queue"),
+        )
+        .expect("Error writing job queue");
+        fs::rename(temp_location, location).expect("Error saving job queue");
+    }
+}
+
+impl Job {
+    fn new(file: &str) -> Job {
+        Job {
+            file: file.to_string(),
+            state: JobState::Pending,
+            error: None,
+        }
+    }
+}
+
+// each batch gets its own queue, named after a hash of its input and output, so starting one batch doesn't lose another's progress
+fn queue_location(input: &str, out: &str) -> PathBuf {
+    let batch_hash = format!("{:x}", Sha256::digest(format!("{}\n{}", input, out)));
+
+    home_dir()
+        .expect("Error getting user's home directory")
+        .join(".project-soap")
+        .join("queues")
+        .join(format!("{}.json", batch_hash))
+}
@@ -0,0 +1,146 @@
+use serde::Serialize;
+use std::{collections::BTreeMap, process::Command};
+
+use crate::backends::Word;
+
+// how much profanity is in a file, without changing it
+#[derive(Serialize)]
+pub struct Rating {
+    file: String,
+    // in seconds
+    duration: f32,
+    hits: usize,
+    hits_per_minute: f32,
+    rating: String,
+    counts_per_category: BTreeMap<String, usize>,
+    worst_segments: Vec<Segment>,
+}
+
+// a stretch of the file with a lot of hits in it
+#[derive(Serialize)]
+struct Segment {
+    start: f32,
+    end: f32,
+    hits: usize,
+}
+
+// how many of the worst segments to list
+const WORST_SEGMENT_COUNT: usize = 3;
+
+impl Rating {
+    // rates the file from the words found in it - bands are (name, max hits per minute) pairs from mildest to harshest - None if ffprobe can't tell how long the file is
+    pub fn new(
+        file_location: &str,
+        hits: &[Word],
+        expletives: &crate::Expletives,
+        bands: &[(String, f32)],
+        window: f32,
+    ) -> Option<Rating> {
+        let duration = media_duration(file_location)?;
+        let hits_per_minute = if duration > 0. {
+            hits.len() as f32 / (duration / 60.)
+        } else {
+            0.
+        };
+
+        // anything past the last band's limit still gets the last band
+        let rating = bands
+            .iter()
+            .find(|(_, max)| hits_per_minute <= *max)
+            .or(bands.last())
+            .map(|(name, _)| name.clone())
+            .unwrap_or_default();
+
+        let mut counts_per_category = BTreeMap::new();
+        for hit in hits {
+            let category = expletives
+                .get(&hit.word)
+                .cloned()
+                .unwrap_or_else(|| String::from(crate::UNCATEGORIZED));
+
+            *counts_per_category.entry(category).or_insert(0) += 1;
+        }
+
+        Some(Rating {
+            file: file_location.to_string(),
+            duration,
+            hits: hits.len(),
+            hits_per_minute,
+            rating,
+            counts_per_category,
+            worst_segments: worst_segments(hits, window),
+        })
+    }
+
+    pub fn print(&self) {
+        println!("{}", self.file);
+        println!(
+            "{} expletives in {:.0} seconds - {:.2} per minute",
+            self.hits, self.duration, self.hits_per_minute
+        );
+        println!("Suggested rating: {}", self.rating);
+
+        for (category, count) in &self.counts_per_category {
+            println!("  {}: {}", category, count);
+        }
+
+        if !self.worst_segments.is_empty() {
+            println!("Worst segments:");
+        }
+        for segment in &self.worst_segments {
+            println!(
+                "  {:.1}s - {:.1}s: {} expletives",
+                segment.start, segment.end, segment.hits
+            );
+        }
+    }
+}
+
+// finds the windows with the most hits in them, without letting them overlap
+fn worst_segments(hits: &[Word], window: f32) -> Vec<Segment> {
+    let mut starts: Vec<f32> = hits.iter().map(|h| h.start).collect();
+    starts.sort_by(|a, b| a.total_cmp(b));
+
+    // every window starts on a hit, since that's where the count can go up
+    let mut candidates: Vec<Segment> = starts
+        .iter()
+        .map(|start| Segment {
+            start: *start,
+            end: start + window,
+            hits: starts
+                .iter()
+                .filter(|s| **s >= *start && **s < start + window)
+                .count(),
+        })
+        .collect();
+    candidates.sort_by(|a, b| b.hits.cmp(&a.hits).then(a.start.total_cmp(&b.start)));
+
+    let mut worst: Vec<Segment> = Vec::new();
+    for candidate in candidates {
+        if worst.len() == WORST_SEGMENT_COUNT {
+            break;
+        }
+
+        if worst
+            .iter()
+            .all(|w| candidate.end <= w.start || candidate.start >= w.end)
+        {
+            worst.push(candidate);
+        }
+    }
+
+    worst
+}
+
+// gets the length of the file in seconds from ffprobe - some files don't say, and ffprobe just gives back N/A
+fn media_duration(file_location: &str) -> Option<f32> {
+    let out = Command::new("ffprobe")
+        .args(["-v", "error"])
+        .args(["-show_entries", "format=duration"])
+        .args(["-of", "default=noprint_wrappers=1:nokey=1"])
+        .arg(file_location)
+        .output()
+        .ok()?;
+
+    String::from_utf8(out.stdout).ok()?.trim().parse().ok()
+}
@@ -0,0 +1,149 @@
+use serde::Serialize;
+use std::{collections::BTreeMap, fs, time::Duration};
+
+use crate::backends::Word;
+
+// everything we know about a run, written out as json with --report
+#[derive(Serialize)]
+pub struct Report {
+    input: String,
+    output: String,
+    backend: String,
+    // so it's possible to tell which version of the list a file was cleaned with
+    list_hash: String,
+    hits: Vec<Hit>,
+    // words the backend wasn't sure enough of to censor, but was too sure of to ignore
+    review: Vec<Hit>,
+    counts_per_word: BTreeMap<String, usize>,
+    counts_per_category: BTreeMap<String, usize>,
+    // in seconds - repeated stages are added together
+    stage_times: BTreeMap<String, f64>,
+    total_time: f64,
+}
+
+#[derive(Serialize)]
+struct Hit {
+    word: String,
+    category: String,
+    start: f32,
+    end: f32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    confidence: Option<f32>,
+    // who said it, if the backend can tell speakers apart
+    #[serde(skip_serializing_if = "Option::is_none")]
+    speaker: Option<String>,
+    // which --repeat pass found it
+    pass: usize,
+}
+
+impl Report {
+    pub fn new(input: &str, output: &str, backend: String) -> Report {
+        Report {
+            input: input.to_string(),
+            output: output.to_string(),
+            backend,
+            list_hash: crate::list_hash(),
+            hits: Vec::new(),
+            review: Vec::new(),
+            counts_per_word: BTreeMap::new(),
+            counts_per_category: BTreeMap::new(),
+            stage_times: BTreeMap::new(),
+            total_time: 0.,
+        }
+    }
+
+    // records the words that were removed, along with the category they came from in the list
+    pub fn add_hits(&mut self, hits: &[Word], expletives: &crate::Expletives, pass: usize) {
+        for hit in hits {
+            let category = category(&hit.word, expletives);
+
+            *self.counts_per_word.entry(hit.word.clone()).or_insert(0) += 1;
+            *self
+                .counts_per_category
+                .entry(category.clone())
+                .or_insert(0) += 1;
+
+            self.hits.push(Hit {
+                word: hit.word.clone(),
+                category,
+                start: hit.start,
+                end: hit.end,
+                confidence: hit.confidence,
+                speaker: hit.speaker.clone(),
+                pass,
+            });
+        }
+    }
+
+    // records words flagged for review - later passes hear the same uncensored words again, so each is only kept once
+    pub fn add_review(&mut self, words: &[Word], expletives: &crate::Expletives, pass: usize) {
+        for word in words {
+            if self
+                .review
+                .iter()
+                .any(|r| r.word == word.word && word.start < r.end && r.start < word.end)
+            {
+                continue;
+            }
+
+            self.review.push(Hit {
+                word: word.word.clone(),
+                category: category(&word.word, expletives),
+                start: word.start,
+                end: word.end,
+                confidence: word.confidence,
+                speaker: word.speaker.clone(),
+                pass,
+            });
+        }
+    }
+
+    // lists the words flagged for review, so someone can go listen to them
+    pub fn print_review(&self) {
+        if self.review.is_empty() {
+            return;
+        }
+
+        println!("{} words to review in {}:", self.review.len(), self.input);
+        for word in &self.review {
+            println!(
+                "  {} at {:.2}s ({:.2} confidence{})",
+                word.word,
+                word.start,
+                word.confidence.unwrap_or_default(),
+                word.speaker
+                    .as_ref()
+                    .map(|s| format!(", {}", s))
+                    .unwrap_or_default()
+            );
+        }
+    }
+
+    pub fn add_stage_time(&mut self, stage: &str, time: Duration) {
+        *self.stage_times.entry(stage.to_string()).or_insert(0.) += time.as_secs_f64();
+    }
+
+    pub fn finish(mut self, total_time: Duration) -> Report {
+        self.total_time = total_time.as_secs_f64();
+        self
+    }
+}
+
+fn category(word: &str, expletives: &crate::Expletives) -> String {
+    expletives
+        .get(word)
+        .cloned()
+        .unwrap_or_else(|| String::from(crate::UNCATEGORIZED))
+}
+
+// writes the reports out as json - a batch gets a list, a single file just gets its own report
+pub fn write<T: Serialize>(reports: &[T], report_location: &str, batch: bool) {
+    let json = if batch {
+        serde_json::to_string_pretty(reports)
+    } else {
+        serde_json::to_string_pretty(&reports[0])
+    };
+
+    fs::write(report_location, json.expect("Error serializing report"))
+        .expect("Error writing report");
+}
@@ -0,0 +1,78 @@
+use std::collections::HashMap;
+
+use crate::{backends::Word, cli, Expletives};
+
+// who gets censored, and which list their words are checked against
+pub struct Policy {
+    // only these speakers are censored - empty means everyone is
+    censor: Vec<String>,
+    // these speakers are never censored
+    keep: Vec<String>,
+    // speakers with their own list instead of the usual one
+    lists: HashMap<String, Expletives>,
+}
+
+impl Policy {
+    pub fn from_args(args: &cli::Args) -> Policy {
+        Policy {
+            censor: args.censor_speakers.clone(),
+            keep: args.keep_speakers.clone(),
+            lists: args
+                .speaker_list
+                .iter()
+                .map(|(speaker, location)| (speaker.clone(), crate::load_expletives_from(location)))
+                .collect(),
+        }
+    }
+
+    // keeps only the words on the list for whoever said them - words nobody could put a speaker to are always checked, so nothing slips through when the backend can't tell
+    pub fn find_expletives(&self, default: &Expletives, words: Vec<Word>) -> Vec<Word> {
+        words
+            .into_iter()
+            .filter(|w| match &w.speaker {
+                None => default.contains_key(&w.word),
+                Some(speaker) => {
+                    self.censors(speaker)
+                        && self
+                            .lists
+                            .get(speaker)
+                            .unwrap_or(default)
+                            .contains_key(&w.word)
+                }
+            })
+            .collect()
+    }
+
+    // every list in one, so words from a speaker's own list still get their category in the report
+    pub fn all_expletives(&self, default: &Expletives) -> Expletives {
+        let mut all = Expletives::new();
+
+        for list in self.lists.values() {
+            all.extend(list.iter().map(|(w, c)| (w.clone(), c.clone())));
+        }
+        // the usual list wins when a word is in both
+        all.extend(default.iter().map(|(w, c)| (w.clone(), c.clone())));
+
+        all
+    }
+
+    // whether any of the speaker options were given
+    fn is_set(&self) -> bool {
+        !self.censor.is_empty() || !self.keep.is_empty() || !self.lists.is_empty()
+    }
+
+    // speaker options do nothing when the backend couldn't tell anyone apart, so it's worth saying so
+    pub fn warn_if_unlabeled(&self, words: &[Word], file_location: &str) {
+        if self.is_set() && !words.is_empty() && words.iter().all(|w| w.speaker.is_none()) {
+            println!(
+                "Warning: the backend didn't say who said anything in {}, so the speaker options do nothing and every word is checked against the usual list - see --speaker-model for vosk-local, or --diarize for whisper-x-local",
+                file_location
+            );
+        }
+    }
+
+    fn censors(&self, speaker: &str) -> bool {
+        !self.keep.iter().any(|k| k == speaker)
+            && (self.censor.is_empty() || self.censor.iter().any(|c| c == speaker))
+    }
+}
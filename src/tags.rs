@@ -0,0 +1,109 @@
+use serde::Deserialize;
+use std::{collections::HashMap, path::Path, process::Command};
+
+// the metadata tags written into every cleaned file, so it can be recognized and skipped on later runs
+pub fn tags(backend: &str, settings: &str) -> Vec<(&'static str, String)> {
+    vec![
+        (
+            "project_soap_version",
+            env!("CARGO_PKG_VERSION").to_string(),
+        ),
+        ("project_soap_list", crate::list_hash()),
+        ("project_soap_backend", backend.to_string()),
+        ("project_soap_settings", settings.to_string()),
+    ]
+}
+
+// the options besides the backend's that change what gets censored
+pub fn settings(args: &crate::cli::Args) -> String {
+    let mut settings = format!(
+        "--censor-above {} --review-above {}",
+        args.censor_above, args.review_above
+    );
+
+    if !args.censor_speakers.is_empty() {
+        settings += &format!(" --censor-speakers {}", args.censor_speakers.join(","));
+    }
+    if !args.keep_speakers.is_empty() {
+        settings += &format!(" --keep-speakers {}", args.keep_speakers.join(","));
+    }
+
+    // the speakers' lists are hashed like the usual one, so editing them counts as a change too
+    let mut speaker_lists: Vec<String> = args
+        .speaker_list
+        .iter()
+        .map(|(speaker, location)| format!("{}={}", speaker, crate::list_hash_of(location)))
+        .collect();
+    speaker_lists.sort();
+    for speaker_list in speaker_lists {
+        settings += &format!(" --speaker-list {}", speaker_list);
+    }
+
+    settings
+}
+
+// the ffmpeg arguments that write the tags into the output
+pub fn ffmpeg_args(out_location: &str, backend: &str, settings: &str) -> Vec<String> {
+    let mut args = Vec::new();
+
+    for (key, value) in tags(backend, settings) {
+        args.push(String::from("-metadata"));
+        args.push(format!("{}={}", key, value));
+    }
+
+    // mp4 and friends drop tags they don't know about unless they're told not to
+    let extension = Path::new(out_location)
+        .extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or_default()
+        .to_lowercase();
+    if ["mp4", "m4a", "m4v", "mov"].contains(&extension.as_str()) {
+        args.push(String::from("-movflags"));
+        args.push(String::from("use_metadata_tags"));
+    }
+
+    args
+}
+
+// whether the file was already cleaned by this version, with this list and these backend settings
+pub fn is_cleaned(file_location: &str, backend: &str, settings: &str) -> bool {
+    #[derive(Deserialize)]
+    struct Probe {
+        format: Format,
+    }
+
+    #[derive(Deserialize)]
+    struct Format {
+        #[serde(default)]
+        tags: HashMap<String, String>,
+    }
+
+    if !Path::new(file_location).exists() {
+        return false;
+    }
+
+    let out = Command::new("ffprobe")
+        .args(["-v", "error"])
+        .args(["-show_entries", "format_tags"])
+        .args(["-of", "json"])
+        .arg(file_location)
+        .output()
+        .expect("FFprobe error");
+
+    let probe: Probe = match serde_json::from_slice(&out.stdout) {
+        Ok(p) => p,
+        Err(_) => return false,
+    };
+
+    // some containers change the case of tag names
+    let found: HashMap<String, String> = probe
+        .format
+        .tags
+        .into_iter()
+        .map(|(k, v)| (k.to_lowercase(), v))
+        .collect();
+
+    tags(backend, settings)
+        .iter()
+        .all(|(key, value)| found.get(*key) == Some(value))
+}
@@ -0,0 +1,134 @@
+use dirs::home_dir;
+use serde::Deserialize;
+use std::{fs, process::Command};
+
+use crate::backends::{Cleaner, Word};
+
+// how far apart the input and output durations can be before something's considered wrong, in seconds
+const DURATION_TOLERANCE: f32 = 0.5;
+
+#[derive(Deserialize)]
+struct Probe {
+    #[serde(default)]
+    streams: Vec<Stream>,
+    format: Option<Format>,
+}
+
+#[derive(Deserialize)]
+struct Stream {
+    codec_type: Option<String>,
+    codec_name: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Format {
+    duration: Option<String>,
+}
+
+// checks the cleaned file looks like the original - same length, same streams, and the same codecs for everything that was copied
+pub fn check_output(file_location: &str, out_location: &str) -> Result<(), String> {
+    let input = probe(file_location).ok_or(format!("Couldn't probe {}", file_location))?;
+    let output = probe(out_location).ok_or(format!(
+        "Couldn't probe the cleaned file at {}",
+        out_location
+    ))?;
+
+    let input_duration = duration(&input).ok_or("Couldn't get the original's duration")?;
+    let output_duration = duration(&output).ok_or("Couldn't get the cleaned file's duration")?;
+
+    if (input_duration - output_duration).abs() > DURATION_TOLERANCE {
+        return Err(format!(
+            "The cleaned file is {:.2}s long, but the original is {:.2}s",
+            output_duration, input_duration
+        ));
+    }
+
+    if input.streams.len() != output.streams.len() {
+        return Err(format!(
+            "The cleaned file has {} streams, but the original has {}",
+            output.streams.len(),
+            input.streams.len()
+        ));
+    }
+
+    for (i, (input_stream, output_stream)) in input.streams.iter().zip(&output.streams).enumerate()
+    {
+        if input_stream.codec_type != output_stream.codec_type {
+            return Err(format!(
+                "Stream {} is {:?} in the cleaned file, but {:?} in the original",
+                i, output_stream.codec_type, input_stream.codec_type
+            ));
+        }
+
+        // the audio is re-encoded, so only the other streams have to keep their codec
+        if input_stream.codec_type.as_deref() != Some("audio")
+            && input_stream.codec_name != output_stream.codec_name
+        {
+            return Err(format!(
+                "Stream {} was encoded as {:?} in the cleaned file, but {:?} in the original",
+                i, output_stream.codec_name, input_stream.codec_name
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+// transcribes just the censored parts of the cleaned file, and checks nothing on the list can be heard in them anymore
+pub fn check_censored(
+    cleaner: &mut dyn Cleaner,
+    out_location: &str,
+    hits: &[Word],
+    expletives: &crate::Expletives,
+) -> Result<(), String> {
+    if hits.is_empty() {
+        return Ok(());
+    }
+
+    let temp_dir = home_dir()
+        .expect("Error getting user's home directory")
+        .join(".project-soap")
+        .join("temp");
+    fs::create_dir_all(&temp_dir).expect("Error making temp directory");
+
+    let intervals_location = temp_dir.join("verify-intervals.wav");
+    let intervals_location = intervals_location
+        .to_str()
+        .expect("Error converting temp path to string");
+
+    // pulls the censored intervals out of the cleaned file and puts them one after another
+    let intervals: Vec<(f32, f32)> = hits.iter().map(|w| (w.start, w.end)).collect();
+    crate::audio::extract_intervals(out_location, &intervals, intervals_location);
+
+    let heard = crate::find_expletives(expletives, cleaner.transcribe(intervals_location));
+    let _ = fs::remove_file(intervals_location);
+
+    if heard.is_empty() {
+        Ok(())
+    } else {
+        let words: Vec<String> = heard.into_iter().map(|w| w.word).collect();
+        Err(format!(
+            "Still heard {} in the censored parts of the cleaned file",
+            words.join(", ")
+        ))
+    }
+}
+
+fn probe(file_location: &str) -> Option<Probe> {
+    let out = Command::new("ffprobe")
+        .args(["-v", "error"])
+        .args([
+            "-show_entries",
+            "format=duration:stream=codec_type,codec_name",
+        ])
+        .args(["-of", "json"])
+        .arg(file_location)
+        .output()
+        .expect("FFprobe error");
+
+    serde_json::from_slice(&out.stdout).ok()
+}
+
+fn duration(probe: &Probe) -> Option<f32> {
+    probe.format.as_ref()?.duration.as_ref()?.parse().ok()
+}
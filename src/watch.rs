@@ -0,0 +1,189 @@
+use dirs::home_dir;
+use std::{
+    collections::{HashMap, HashSet},
+    fs::{self, OpenOptions},
+    io::Write,
+    panic::{self, AssertUnwindSafe},
+    path::{Path, PathBuf},
+    thread,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use crate::{backends::Cleaner, cli, Outcome};
+
+// how often the watched directory is checked for new files
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+// watches the directory for new files, cleaning each one into the output directory once it's done being written - this never returns
+pub fn watch(cleaner: &mut dyn Cleaner, watch_dir: &str, args: &cli::Args) {
+    let out_dir = if args.out.is_empty() {
+        Path::new(watch_dir).join("cleaned")
+    } else {
+        PathBuf::from(&args.out)
+    };
+    fs::create_dir_all(&out_dir).expect("Error making output directory");
+
+    println!(
+        "Watching {} - cleaned files will be put in {}",
+        watch_dir,
+        out_dir.display()
+    );
+
+    // the size and modified time of each file the last time we looked, and how long it's been that way
+    let mut seen: HashMap<PathBuf, (u64, SystemTime, Instant)> = HashMap::new();
+    // files that failed or were skipped - they're left alone unless they change
+    let mut failed: HashSet<(PathBuf, u64, SystemTime)> = HashSet::new();
+
+    loop {
+        for (file, size, modified) in list_files(watch_dir, &args.extensions) {
+            if failed.contains(&(file.clone(), size, modified)) {
+                continue;
+            }
+
+            // a file is only done being written once it stops changing for long enough
+            let settled_since = match seen.get(&file) {
+                Some((s, m, since)) if *s == size && *m == modified => *since,
+                _ => {
+                    seen.insert(file.clone(), (size, modified, Instant::now()));
+                    continue;
+                }
+            };
+
+            if settled_since.elapsed() < Duration::from_secs(args.settle) {
+                continue;
+            }
+
+            seen.remove(&file);
+
+            if !process(cleaner, &file, &out_dir, args) {
+                failed.insert((file, size, modified));
+            }
+        }
+
+        thread::sleep(POLL_INTERVAL);
+    }
+}
+
+// cleans one file into the output directory and takes it out of the watched directory - returns false if it failed or was skipped, and was left where it is
+fn process(cleaner: &mut dyn Cleaner, file: &Path, out_dir: &Path, args: &cli::Args) -> bool {
+    let file_location = file.to_str().expect("Error converting file path to string");
+    let out_location = out_dir.join(file.file_name().expect("Error getting file name"));
+
+    println!("Cleaning {}", file_location);
+    let start = Instant::now();
+    let started_at = SystemTime::now();
+
+    // a bad file shouldn't take the whole watcher down with it
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        crate::clean_file(
+            cleaner,
+            file_location,
+            out_location
+                .to_str()
+                .expect("Error converting output path to string")
+                .to_string(),
+            args,
+            &mut |_| {},
+        )
+    }));
+
+    // ffmpeg doesn't always fail loudly, so the output has to actually have been written for the job to count
+    let written = fs::metadata(&out_location)
+        .and_then(|m| m.modified())
+        .is_ok_and(|modified| modified >= started_at);
+
+    match result {
+        // the output's already been cleaned with this list and these settings - moving this over it would put the uncensored original back
+        Ok((Outcome::Skipped, _)) => {
+            log(&format!(
+                "{}	already cleaned in {}, left in place",
+                file_location,
+                out_location.display()
+            ));
+            println!(
+                "{} is already cleaned in {}, leaving it where it is",
+                file_location,
+                out_location.display()
+            );
+            false
+        }
+        Ok((Outcome::Cleaned(count, _), _)) if count == 0 || written => {
+            // files with nothing to remove aren't written out by ffmpeg, so they're moved over as they are
+            if count == 0 {
+                move_file(file, &out_location);
+            } else {
+                fs::remove_file(file).expect("Error removing cleaned file from watched directory");
+            }
+
+            log(&format!(
+                "{}\tremoved {} expletives\t{:.1}s",
+                file_location,
+                count,
+                start.elapsed().as_secs_f32()
+            ));
+            println!("Removed {} expletives from {}", count, file_location);
+            true
+        }
+        _ => {
+            log(&format!("{}\tfailed", file_location));
+            println!("Failed to clean {}", file_location);
+            false
+        }
+    }
+}
+
+// the files currently in the watched directory with one of the extensions, along with their size and modified time
+fn list_files(watch_dir: &str, extensions: &[String]) -> Vec<(PathBuf, u64, SystemTime)> {
+    let entries = match fs::read_dir(watch_dir) {
+        Ok(e) => e,
+        Err(_) => return Vec::new(),
+    };
+
+    entries
+        .flatten()
+        .filter_map(|entry| {
+            let metadata = entry.metadata().ok()?;
+            let path = entry.path();
+            let extension = path.extension()?.to_str()?;
+            let hidden = path.file_name()?.to_str()?.starts_with('.');
+
+            if metadata.is_file()
+                && !hidden
+                && extensions.iter().any(|x| x.eq_ignore_ascii_case(extension))
+            {
+                Some((path, metadata.len(), metadata.modified().ok()?))
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
+// renames the file, falling back to copying it if the output directory is on another drive
+fn move_file(from: &Path, to: &Path) {
+    if fs::rename(from, to).is_err() {
+        fs::copy(from, to).expect("Error copying file to output directory");
+        fs::remove_file(from).expect("Error removing file from watched directory");
+    }
+}
+
+// adds a line to the watch log at ~/.project-soap/watch.log
+fn log(line: &str) {
+    let soap_dir = home_dir()
+        .expect("Error getting user's home directory")
+        .join(".project-soap");
+    fs::create_dir_all(&soap_dir).expect("Error making ~/.project-soap");
+
+    let time = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("Error getting the time")
+        .as_secs();
+
+    let mut log_file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(soap_dir.join("watch.log"))
+        .expect("Error opening watch log");
+
+    writeln!(log_file, "{}\t{}", time, line).expect("Error writing to watch log");
+}